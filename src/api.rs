@@ -0,0 +1,489 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::config::{ApiResolved, HISTORY_DIDS, TopicsResolved};
+use crate::dtc::ActiveDtc;
+use crate::event::EventRecord;
+use crate::influxdb::InfluxStatus;
+use crate::store::{EventFilter, parse_time_arg};
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+/// New events buffered for slow stream clients before they miss some.
+const LIVE_CAPACITY: usize = 256;
+
+/// State of the daemon as shown by the API; updated by the main loop.
+pub type SharedState = Arc<Mutex<ApiState>>;
+
+/// Everything the API shows: connection states, ECUs and the recent events.
+pub struct ApiState {
+    started_at: DateTime<Utc>,
+    mqtt: MqttStatus,
+    influx: Option<Arc<Mutex<InfluxStatus>>>,
+    ecus: Vec<EcuStatus>,
+    /// Recent events, oldest first
+    events: VecDeque<EventRecord>,
+    max_events: usize,
+    /// New events for `/events/stream`
+    live: broadcast::Sender<EventRecord>,
+}
+
+/// Connection to the MQTT broker.
+#[derive(Clone, Debug, Serialize)]
+pub struct MqttStatus {
+    /// Host name of the broker
+    pub host: String,
+    /// Whether the broker accepted the connection and it did not break since
+    pub connected: bool,
+    /// When the last history list was received
+    pub last_message: Option<DateTime<Utc>>,
+}
+
+/// One currently active fault of an ECU.
+#[derive(Clone, Debug, Serialize)]
+pub struct ActiveFault {
+    /// Entry type as sent by open3e (usually `Error`)
+    #[serde(rename = "type")]
+    pub state_type: String,
+    /// Syslog severity, e.g. `err`
+    pub severity: String,
+    /// Message code, e.g. `F.221`
+    pub code: String,
+    /// Numeric ID of the message
+    pub id: i64,
+    /// open3e name of the message
+    pub text: String,
+}
+
+impl ActiveFault {
+    fn new(e: &ActiveDtc) -> Self {
+        Self {
+            state_type: e.state_type.clone(),
+            severity: e.get_severity(),
+            code: e.get_msg_code(),
+            id: e.state.id,
+            text: e.state.text.clone(),
+        }
+    }
+}
+
+/// One configured ECU as shown by `/ecus`.
+#[derive(Clone, Debug, Serialize)]
+pub struct EcuStatus {
+    /// ECU id as written to the outputs
+    pub ecuid: String,
+    /// open3e address (e.g. `0x680`), if configured
+    pub address: Option<String>,
+    /// The ECU answered at least once since the start
+    pub discovered: bool,
+    /// When the last history list of this ECU was received
+    pub last_poll: Option<DateTime<Utc>>,
+    /// Entries of the last received list of active errors; `None` before it arrived
+    pub active_faults: Option<Vec<ActiveFault>>,
+    /// Entries of the last received list per history DID
+    #[serde(skip)]
+    pub lists: BTreeMap<u16, Vec<EventRecord>>,
+}
+
+impl ApiState {
+    /// State of the configured ECUs in `topics`; `max_events` recent events are kept.
+    pub fn new(topics: &TopicsResolved, mqtt_host: &str, max_events: usize) -> Self {
+        let mut state = Self {
+            started_at: Utc::now(),
+            mqtt: MqttStatus {
+                host: mqtt_host.to_string(),
+                connected: false,
+                last_message: None,
+            },
+            influx: None,
+            ecus: Vec::new(),
+            events: VecDeque::new(),
+            max_events: max_events.max(1),
+            live: broadcast::channel(LIVE_CAPACITY).0,
+        };
+        state.set_topics(topics);
+        state
+    }
+
+    /// Wraps the state for sharing between the main loop and the API handlers.
+    pub fn shared(self) -> SharedState {
+        Arc::new(Mutex::new(self))
+    }
+
+    /// Replaces the configured ECUs; the status of ECUs that are kept stays.
+    pub fn set_topics(&mut self, topics: &TopicsResolved) {
+        let mut old = std::mem::take(&mut self.ecus);
+        self.ecus = topics
+            .ecus
+            .iter()
+            .map(|ecu| match old.iter().position(|s| s.ecuid == ecu.ecuid) {
+                Some(i) => EcuStatus {
+                    address: ecu.address.clone(),
+                    ..old.swap_remove(i)
+                },
+                None => EcuStatus {
+                    ecuid: ecu.ecuid.clone(),
+                    address: ecu.address.clone(),
+                    discovered: false,
+                    last_poll: None,
+                    active_faults: None,
+                    lists: BTreeMap::new(),
+                },
+            })
+            .collect();
+    }
+
+    /// Sets the status of the InfluxDB writer (`None` if InfluxDB is disabled).
+    pub fn set_influx(&mut self, status: Option<Arc<Mutex<InfluxStatus>>>) {
+        self.influx = status;
+    }
+
+    /// The broker accepted the connection (or sent anything else).
+    pub fn mqtt_connected(&mut self) {
+        self.mqtt.connected = true;
+    }
+
+    /// The connection broke; the event loop reconnects.
+    pub fn mqtt_disconnected(&mut self) {
+        self.mqtt.connected = false;
+    }
+
+    /// Records the list of active errors of `ecuid`.
+    pub fn record_active_faults(
+        &mut self,
+        ecuid: &str,
+        entries: &[ActiveDtc],
+        received_at: DateTime<Utc>,
+    ) {
+        self.mqtt.connected = true;
+        self.mqtt.last_message = Some(received_at);
+        if let Some(ecu) = self.ecus.iter_mut().find(|e| e.ecuid == ecuid) {
+            ecu.discovered = true;
+            ecu.active_faults = Some(entries.iter().map(ActiveFault::new).collect());
+        }
+    }
+
+    /// Records one received history list of `ecuid`: `new` are the entries emitted for
+    /// it, `list` all entries of the list.
+    pub fn record_poll(
+        &mut self,
+        ecuid: &str,
+        did: u16,
+        new: Vec<EventRecord>,
+        list: Vec<EventRecord>,
+        received_at: DateTime<Utc>,
+    ) {
+        self.mqtt.connected = true;
+        self.mqtt.last_message = Some(received_at);
+        if let Some(ecu) = self.ecus.iter_mut().find(|e| e.ecuid == ecuid) {
+            ecu.discovered = true;
+            ecu.last_poll = Some(received_at);
+            ecu.lists.insert(did, list);
+        }
+        for record in &new {
+            // no receivers is fine
+            let _ = self.live.send(record.clone());
+        }
+        self.events.extend(new);
+        while self.events.len() > self.max_events {
+            self.events.pop_front();
+        }
+    }
+}
+
+/// Builds the router; with a `token` every request needs `Authorization: Bearer <token>`,
+/// except for the web page at `/` (it asks for the token and holds no data itself).
+pub fn router(state: SharedState, token: Option<String>) -> Router {
+    let data = Router::new()
+        .route("/events", get(events))
+        .route("/events/stream", get(events_stream))
+        .route("/ecus", get(ecus))
+        .route("/ecus/{ecuid}/history", get(ecu_history))
+        .route("/status", get(status))
+        .with_state(state);
+    let data = match token {
+        Some(token) => data.layer(middleware::from_fn_with_state(Arc::new(token), auth)),
+        None => data,
+    };
+    Router::new().route("/", get(web_ui)).merge(data)
+}
+
+/// Starts the HTTP API in the background.
+pub async fn serve(cfg: &ApiResolved, state: SharedState) -> std::io::Result<()> {
+    let listener = tokio::net::TcpListener::bind(&cfg.listen).await?;
+    log::info!(
+        "HTTP API listening on {}{}",
+        listener.local_addr()?,
+        if cfg.token.is_some() {
+            " (bearer token required)"
+        } else {
+            ""
+        }
+    );
+    let app = router(state, cfg.token.clone());
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            log::error!("HTTP API stopped: {}", e);
+        }
+    });
+    Ok(())
+}
+
+async fn auth(State(token): State<Arc<String>>, request: Request, next: Next) -> Response {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|t| constant_time_eq(t.as_bytes(), token.as_bytes()));
+    if !authorized {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            Json(ErrorBody {
+                error: "missing or wrong bearer token".to_string(),
+            }),
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+fn bad_request(error: String) -> Response {
+    (StatusCode::BAD_REQUEST, Json(ErrorBody { error })).into_response()
+}
+
+/// Filters shared by `/events` and `/events/stream`.
+#[derive(Debug, Deserialize)]
+struct FilterQuery {
+    since: Option<String>,
+    until: Option<String>,
+    code: Option<String>,
+    ecu: Option<String>,
+    severity: Option<String>,
+}
+
+impl FilterQuery {
+    fn filter(self) -> Result<EventFilter, String> {
+        let now = Utc::now();
+        let time = |value: Option<String>| value.map(|v| parse_time_arg(&v, now)).transpose();
+        Ok(EventFilter {
+            since: time(self.since)?,
+            until: time(self.until)?,
+            code: self.code,
+            ecuid: self.ecu,
+            severity: self.severity,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PageQuery {
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct EventsPage {
+    /// Number of matching events
+    total: usize,
+    offset: usize,
+    limit: usize,
+    /// Newest first
+    events: Vec<EventRecord>,
+}
+
+async fn events(
+    State(state): State<SharedState>,
+    Query(filter): Query<FilterQuery>,
+    Query(query): Query<PageQuery>,
+) -> Response {
+    let filter = match filter.filter() {
+        Ok(filter) => filter,
+        Err(e) => return bad_request(e),
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+
+    let state = state.lock().unwrap();
+    let matching: Vec<&EventRecord> = state
+        .events
+        .iter()
+        .rev()
+        .filter(|r| filter.matches(r))
+        .collect();
+    Json(EventsPage {
+        total: matching.len(),
+        offset,
+        limit,
+        events: matching
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .cloned()
+            .collect(),
+    })
+    .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamQuery {
+    /// Matching events from memory to send first
+    last: Option<usize>,
+}
+
+/// Server-Sent Events with every new matching event (`event: dtc`, the record as JSON),
+/// after the last `last` matching events already held in memory.
+async fn events_stream(
+    State(state): State<SharedState>,
+    Query(filter): Query<FilterQuery>,
+    Query(query): Query<StreamQuery>,
+) -> Response {
+    let filter = match filter.filter() {
+        Ok(filter) => filter,
+        Err(e) => return bad_request(e),
+    };
+
+    // subscribe under the same lock as the replay, so no event is missed or sent twice
+    let (replay, live) = {
+        let state = state.lock().unwrap();
+        let mut replay: Vec<EventRecord> = state
+            .events
+            .iter()
+            .rev()
+            .filter(|r| filter.matches(r))
+            .take(query.last.unwrap_or(0))
+            .cloned()
+            .collect();
+        replay.reverse();
+        (replay, state.live.subscribe())
+    };
+
+    let live = stream::unfold(live, |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(record) => return Some((record, rx)),
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    log::warn!("Event stream client too slow, {} events skipped", missed)
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+    .filter(move |r| std::future::ready(filter.matches(r)));
+
+    Sse::new(sse_events(stream::iter(replay).chain(live)))
+        .keep_alive(KeepAlive::new().interval(Duration::from_secs(15)))
+        .into_response()
+}
+
+fn sse_events(
+    records: impl Stream<Item = EventRecord>,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    records.map(|r| {
+        Ok(Event::default()
+            .event("dtc")
+            .id(r.event_id.clone())
+            .json_data(&r)
+            .unwrap_or_default())
+    })
+}
+
+async fn ecus(State(state): State<SharedState>) -> Json<Vec<EcuStatus>> {
+    Json(state.lock().unwrap().ecus.clone())
+}
+
+#[derive(Serialize)]
+struct HistoryList {
+    did: u16,
+    name: &'static str,
+    /// In the order of the received list
+    entries: Vec<EventRecord>,
+}
+
+/// The last received list of every history DID of one ECU.
+async fn ecu_history(State(state): State<SharedState>, Path(ecuid): Path<String>) -> Response {
+    let state = state.lock().unwrap();
+    let Some(ecu) = state.ecus.iter().find(|e| e.ecuid == ecuid) else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(ErrorBody {
+                error: format!("unknown ECU '{}'", ecuid),
+            }),
+        )
+            .into_response();
+    };
+    let lists: Vec<HistoryList> = HISTORY_DIDS
+        .iter()
+        .filter_map(|(did, name)| {
+            ecu.lists.get(did).map(|entries| HistoryList {
+                did: *did,
+                name,
+                entries: entries.clone(),
+            })
+        })
+        .collect();
+    Json(lists).into_response()
+}
+
+/// Single-page status view for the browser, built on the endpoints above.
+async fn web_ui() -> Html<&'static str> {
+    Html(include_str!("web_ui.html"))
+}
+
+#[derive(Serialize)]
+struct StatusBody {
+    version: &'static str,
+    started_at: DateTime<Utc>,
+    uptime_secs: i64,
+    mqtt: MqttStatus,
+    influxdb: InfluxBody,
+}
+
+#[derive(Serialize)]
+struct InfluxBody {
+    enabled: bool,
+    /// Whether the last write succeeded; `null` before the first write
+    ok: Option<bool>,
+    #[serde(flatten)]
+    status: InfluxStatus,
+}
+
+async fn status(State(state): State<SharedState>) -> Json<StatusBody> {
+    let state = state.lock().unwrap();
+    let influx = state.influx.as_ref().map(|s| s.lock().unwrap().clone());
+    Json(StatusBody {
+        version: env!("CARGO_PKG_VERSION"),
+        started_at: state.started_at,
+        uptime_secs: (Utc::now() - state.started_at).num_seconds(),
+        mqtt: state.mqtt.clone(),
+        influxdb: InfluxBody {
+            enabled: influx.is_some(),
+            ok: influx.as_ref().and_then(InfluxStatus::ok),
+            status: influx.unwrap_or_default(),
+        },
+    })
+}
@@ -0,0 +1,58 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::config::CaptureResolved;
+use crate::rotate::RotatingFile;
+
+/// One received publish as written to the capture file.
+/// The same format is read back by `vilog replay`.
+#[derive(Debug, Serialize)]
+pub struct CaptureRecord<'a> {
+    /// MQTT topic of the publish
+    pub topic: &'a str,
+    /// Host time when the publish arrived
+    pub received_at: DateTime<Utc>,
+    /// Raw payload
+    pub payload: &'a str,
+    /// Retain flag of the publish
+    pub retain: bool,
+}
+
+/// Appends every received MQTT publish to a rotating JSONL file.
+pub struct CaptureWriter {
+    file: RotatingFile,
+}
+
+impl CaptureWriter {
+    /// Writer for the capture file of `cfg`; the file is opened with the first record.
+    pub fn new(cfg: &CaptureResolved) -> Self {
+        Self {
+            file: RotatingFile::new(&cfg.path, cfg.max_bytes, cfg.max_files),
+        }
+    }
+
+    /// Appends one received publish; errors are logged, not returned.
+    pub fn record(&mut self, topic: &str, payload: &str, retain: bool) {
+        let record = CaptureRecord {
+            topic,
+            received_at: Utc::now(),
+            payload,
+            retain,
+        };
+        let line = match serde_json::to_string(&record) {
+            Ok(l) => l,
+            Err(e) => {
+                log::error!("Failed to serialize capture record: {}", e);
+                return;
+            }
+        };
+        // flush every record, so the capture is complete even if the process is killed
+        if let Err(e) = self.file.write_line(&line).and_then(|_| self.file.flush()) {
+            log::error!(
+                "Writing capture file '{}' failed: {}",
+                self.file.path().display(),
+                e
+            );
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use std::path::PathBuf;
+
+use vilog::store::{EventFilter, parse_time_arg};
+
+pub const USAGE: &str = "\
+Usage:
+  vilog                              run the logger (MQTT live data)
+  vilog replay <dir|file.jsonl> [--realtime]
+                                     replay captured open3e payloads
+  vilog check-config [file]          check the configuration (strict) and exit
+  vilog query [--since T] [--until T] [--code F.*] [--ecu ID] [--severity S]
+              [--format table|json]  show events from the event store ([store])
+  vilog help                         show this help
+
+The configuration is read from ./vilog.toml or the path in VILOG_CONFIG.
+Unknown config keys are errors; with VILOG_STRICT=0 they are only warnings.
+Times for --since/--until: 2025-11-13T10:00:00Z, 2025-11-13 (UTC) or 30m, 12h, 7d ago.";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Run,
+    Replay { path: PathBuf, realtime: bool },
+    CheckConfig { path: Option<PathBuf> },
+    Query { filter: EventFilter, json: bool },
+    Help,
+}
+
+/// Parses the command line (without the program name).
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut args = args.into_iter();
+
+    match args.next().as_deref() {
+        None => Ok(Command::Run),
+        Some("help" | "-h" | "--help") => Ok(Command::Help),
+        Some("replay") => {
+            let mut path = None;
+            let mut realtime = false;
+            for arg in args {
+                match arg.as_str() {
+                    "--realtime" => realtime = true,
+                    _ if arg.starts_with("--") => return Err(format!("unknown option '{}'", arg)),
+                    _ if path.is_none() => path = Some(PathBuf::from(arg)),
+                    _ => return Err(format!("unexpected argument '{}'", arg)),
+                }
+            }
+            let path = path.ok_or("replay needs a directory or JSONL file")?;
+            Ok(Command::Replay { path, realtime })
+        }
+        Some("check-config") => {
+            let path = args.next().map(PathBuf::from);
+            if let Some(extra) = args.next() {
+                return Err(format!("unexpected argument '{}'", extra));
+            }
+            Ok(Command::CheckConfig { path })
+        }
+        Some("query") => {
+            let now = chrono::Utc::now();
+            let mut filter = EventFilter::default();
+            let mut json = false;
+            while let Some(arg) = args.next() {
+                let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+                match arg.as_str() {
+                    "--since" => filter.since = Some(parse_time_arg(&value()?, now)?),
+                    "--until" => filter.until = Some(parse_time_arg(&value()?, now)?),
+                    "--code" => filter.code = Some(value()?),
+                    "--ecu" => filter.ecuid = Some(value()?),
+                    "--severity" => filter.severity = Some(value()?),
+                    "--format" => {
+                        json = match value()?.as_str() {
+                            "table" => false,
+                            "json" => true,
+                            other => return Err(format!("unknown query format '{}'", other)),
+                        }
+                    }
+                    _ => return Err(format!("unknown option '{}'", arg)),
+                }
+            }
+            Ok(Command::Query { filter, json })
+        }
+        Some(other) => Err(format!("unknown command '{}'", other)),
+    }
+}
@@ -0,0 +1,145 @@
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::dtc::ListEntryDtc;
+
+/// Format of `EntryDateTime::date_time`, e.g. "2025-11-09 18:50:06".
+pub const DEVICE_DATE_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// How the timestamp of an entry is determined.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TimeMode {
+    /// Use the device `Timestamp` as is.
+    Device,
+    /// Interpret the device-local `DateTime` string as local time in the given zone.
+    Timezone(Tz),
+    /// Add a fixed correction (`offset_secs`) to the device `Timestamp`.
+    Offset,
+    /// Estimate the device clock skew against the host time from freshly
+    /// appearing entries. The estimate is rounded to `round_secs`.
+    Auto {
+        /// Step the estimate is rounded to, in seconds
+        round_secs: i64,
+    },
+}
+
+impl TimeMode {
+    /// Parses `[time] mode`; `timezone` is needed for the `timezone` mode.
+    pub fn parse(mode: &str, timezone: Option<&str>, round_secs: i64) -> Result<Self, String> {
+        match mode.trim().to_ascii_lowercase().as_str() {
+            "device" => Ok(Self::Device),
+            "timezone" => {
+                let name = timezone.ok_or("time mode \"timezone\" needs a timezone")?;
+                let tz = name
+                    .parse::<Tz>()
+                    .map_err(|_| format!("unknown timezone '{}'", name))?;
+                Ok(Self::Timezone(tz))
+            }
+            "offset" => Ok(Self::Offset),
+            "auto" => Ok(Self::Auto { round_secs }),
+            other => Err(format!("unknown time mode '{}'", other)),
+        }
+    }
+}
+
+/// Parses the device-local date/time string of an entry.
+pub fn parse_device_date_time(s: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(s.trim(), DEVICE_DATE_TIME_FORMAT).ok()
+}
+
+/// Sets `ListEntryDtc::corrected_timestamp` according to the configured [`TimeMode`].
+pub struct ClockCorrector {
+    mode: TimeMode,
+    /// Correction in ms that is added to the device timestamp (`Offset` and `Auto`;
+    /// in `Auto` mode `offset_secs` is the initial guess until the first estimate).
+    offset_ms: i64,
+}
+
+impl ClockCorrector {
+    /// Corrector for `mode`; `offset_secs` is the fixed (or initial) correction.
+    pub fn new(mode: TimeMode, offset_secs: i64) -> Self {
+        Self {
+            mode,
+            offset_ms: offset_secs.saturating_mul(1000),
+        }
+    }
+
+    /// Corrects the timestamps of `entries`. Runs after the validation: a timestamp it
+    /// rebuilt (`corrected_timestamp`) is corrected instead of the device timestamp.
+    ///
+    /// `fresh` tells whether the entries appeared since the previous poll (and not
+    /// just in the first history list after startup or after a restored state); only those are used to
+    /// estimate the skew in `Auto` mode, because their real time is close to `received_at`.
+    pub fn correct(
+        &mut self,
+        entries: &mut [ListEntryDtc],
+        received_at: DateTime<Utc>,
+        fresh: bool,
+    ) {
+        match &self.mode {
+            TimeMode::Device => {}
+            TimeMode::Timezone(tz) => {
+                for e in entries.iter_mut() {
+                    let local = parse_device_date_time(&e.date_time.date_time)
+                        .and_then(|naive| tz.from_local_datetime(&naive).earliest());
+                    match local {
+                        Some(dt) => e.corrected_timestamp = Some(dt.timestamp_millis()),
+                        None => log::warn!(
+                            "Can not interpret '{}' in timezone {}; using device timestamp",
+                            e.date_time.date_time,
+                            tz
+                        ),
+                    }
+                }
+            }
+            TimeMode::Offset => self.apply_offset(entries),
+            TimeMode::Auto { round_secs } => {
+                // only entries with a plausible device timestamp tell the skew
+                let newest = entries
+                    .iter()
+                    .filter(|e| e.corrected_timestamp.is_none() && !e.suspect)
+                    .map(|e| e.date_time.timestamp)
+                    .max();
+                if fresh
+                    && let Some(skew) =
+                        newest.and_then(|n| n.checked_sub(received_at.timestamp_millis()))
+                {
+                    let skew_ms = round_to(skew, round_secs.saturating_mul(1000));
+                    if skew_ms.saturating_neg() != self.offset_ms {
+                        log::info!(
+                            "Device clock skew estimated at {} s; correcting timestamps by {} s",
+                            skew_ms / 1000,
+                            skew_ms.saturating_neg() / 1000
+                        );
+                        self.offset_ms = skew_ms.saturating_neg();
+                    }
+                }
+                self.apply_offset(entries);
+            }
+        }
+    }
+
+    fn apply_offset(&self, entries: &mut [ListEntryDtc]) {
+        if self.offset_ms == 0 {
+            return;
+        }
+        for e in entries.iter_mut() {
+            match e.timestamp_ms().checked_add(self.offset_ms) {
+                Some(ts) => e.corrected_timestamp = Some(ts),
+                None => log::warn!(
+                    "Can not correct timestamp {} of {} by {} s; using it as is",
+                    e.timestamp_ms(),
+                    e.get_msg_code(),
+                    self.offset_ms / 1000
+                ),
+            }
+        }
+    }
+}
+
+fn round_to(value: i64, step: i64) -> i64 {
+    if step <= 0 {
+        return value;
+    }
+    (value as f64 / step as f64).round() as i64 * step
+}
@@ -1,110 +1,945 @@
-use rumqttc::v5::MqttOptions;
+use crate::clock::TimeMode;
+use crate::config_check::{ConfigIssue, check_values, unknown_keys};
+use crate::config_env::apply_env_overrides;
+use crate::format::OutputFormat;
+use crate::influxdb::{InfluxAttr, InfluxSchema};
+use crate::mqtt;
+use crate::mqtt::MqttProtocol;
+use crate::tls::create_transport;
+use crate::validate::ValidationPolicy;
+use rumqttc::{QoS, Transport};
 use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
 use std::fs;
-use std::time::Duration;
 
-#[derive(Debug, Deserialize)]
+/// The config file (`vilog.toml`); every section is optional.
+#[derive(Debug, Default, Deserialize, Clone, PartialEq)]
 pub struct AppConfig {
+    /// `[mqtt]`
     pub mqtt: Option<MqttConfig>,
+    /// `[topics]`
     pub topics: Option<TopicsConfig>,
+    /// `[influxdb]`
     pub influxdb: Option<InfluxConfig>,
+    /// `[capture]`
+    pub capture: Option<CaptureConfig>,
+    /// `[store]`
+    pub store: Option<StoreConfig>,
+    /// `[logfile]`
+    pub logfile: Option<LogFileConfig>,
+    /// `[output]`
+    pub output: Option<OutputConfig>,
+    /// `[time]`
+    pub time: Option<TimeConfig>,
+    /// `[validation]`
+    pub validation: Option<ValidationConfig>,
+    /// `[reload]`
+    pub reload: Option<ReloadConfig>,
+    /// `[api]`
+    pub api: Option<ApiConfig>,
+    /// `[systemd]`
+    pub systemd: Option<SystemdConfig>,
+    /// `[state]`
+    pub state: Option<StateConfig>,
+    /// `[shutdown]`
+    pub shutdown: Option<ShutdownConfig>,
+    /// `[[ecus]]`
+    pub ecus: Option<Vec<EcuConfig>>,
 }
 
-#[derive(Debug, Deserialize)]
+/// `[mqtt]`: broker connection.
+#[derive(Deserialize, Clone, PartialEq)]
 pub struct MqttConfig {
+    /// Client id
     pub client_id: Option<String>,
+    /// Broker host/IP, or a `ws://`/`wss://` URL
     pub host: Option<String>,
+    /// Broker port (not used for WebSockets)
     pub port: Option<u16>,
+    /// User name
     pub username: Option<String>,
+    /// Password
     pub password: Option<String>,
+    /// Keep-alive of the connection
     pub keep_alive_secs: Option<u64>,
+    /// `v5` or `v311`
+    pub protocol: Option<String>,
+    /// QoS of the subscriptions
+    pub subscribe_qos: Option<u8>,
+    /// QoS of the command messages
+    pub command_qos: Option<u8>,
+    /// Keep the session on the broker while ViLog is offline
+    pub persistent_session: Option<bool>,
+    /// How long the broker keeps the session (v5 only)
+    pub session_expiry_secs: Option<u32>,
+    /// Use TLS
+    pub tls: Option<bool>,
+    /// PEM file with the CA certificate of the broker
+    pub ca_path: Option<String>,
+    /// Client certificate (PEM)
+    pub client_cert_path: Option<String>,
+    /// Private key of the client certificate (PEM, PKCS#8)
+    pub client_key_path: Option<String>,
+    /// ALPN protocols to offer
+    pub alpn: Option<Vec<String>>,
+    /// Accept any certificate and host name (testing only)
+    pub accept_invalid_certs: Option<bool>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// `[topics]`: open3e topics, ids and the poll schedule.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct TopicsConfig {
+    /// Topic of the error history (DID 266)
     pub error: Option<String>,
+    /// Topic of the warning history (DID 264)
     pub warning: Option<String>,
+    /// Topic of the service history (DID 262)
     pub service: Option<String>,
+    /// Topic of the info history (DID 260)
     pub info: Option<String>,
+    /// Topic of the status history (DID 258)
     pub status: Option<String>,
+    /// Topic of the active errors (DID 265)
+    pub active_errors: Option<String>,
+    /// Name/id of the ECU
     pub ecuid: Option<String>,
+    /// Name/id of the heat pump
     pub systemid: Option<String>,
+    /// open3e command topic
     pub command_topic: Option<String>,
+    /// Command message (JSON)
     pub command_payload: Option<String>,
+    /// Interval of the command
     pub command_interval_secs: Option<u64>,
+    /// Delay between the first commands of consecutive ECUs
+    pub stagger_secs: Option<u64>,
+    /// Interval of a command after a possible gap
+    pub gap_interval_secs: Option<u64>,
+    /// Polls without new entries that end the gap interval
+    pub gap_quiet_polls: Option<u32>,
 }
 
-#[derive(Clone, Debug)]
+/// One `[[ecus]]` entry: an ECU with its own history topics and poll schedule.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct EcuConfig {
+    /// Name/id of the ECU; defaults to the address
+    pub ecuid: Option<String>,
+    /// open3e ECU address (e.g. `0x680`), derives the topics
+    pub address: Option<String>,
+    /// Topic of the error history (DID 266)
+    pub error: Option<String>,
+    /// Topic of the warning history (DID 264)
+    pub warning: Option<String>,
+    /// Topic of the service history (DID 262)
+    pub service: Option<String>,
+    /// Topic of the info history (DID 260)
+    pub info: Option<String>,
+    /// Topic of the status history (DID 258)
+    pub status: Option<String>,
+    /// Topic of the active errors (DID 265)
+    pub active_errors: Option<String>,
+    /// `[[ecus.commands]]`
+    pub commands: Option<Vec<CommandConfig>>,
+    /// Extra InfluxDB tags of this ECU
+    pub static_tags: Option<BTreeMap<String, String>>,
+}
+
+/// One `[[ecus.commands]]` entry: DIDs that are read together on their own interval.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct CommandConfig {
+    /// DIDs to read
+    pub dids: Option<Vec<u16>>,
+    /// Command message (JSON); generated from the DIDs if unset
+    pub payload: Option<String>,
+    /// Interval of the command
+    pub interval_secs: Option<u64>,
+    /// Random delay of up to this many seconds for each run
+    pub jitter_secs: Option<u64>,
+}
+
+/// The `[topics]` settings and the `[[ecus]]` to run with.
+#[derive(Clone, Debug, PartialEq)]
 pub struct TopicsResolved {
+    /// Name/id of the heat pump
+    pub systemid: String,
+    /// open3e command topic
+    pub command_topic: String,
+    /// Delay between the first commands of consecutive ECUs
+    pub stagger_secs: u64,
+    /// Interval of a command after a possible gap in one of its history lists
+    pub gap_interval_secs: u64,
+    /// Polls without new entries after which a command returns to its own interval
+    pub gap_quiet_polls: u32,
+    /// Read and subscribe the active errors; only the HTTP API shows them
+    pub active_errors: bool,
+    /// The `[[ecus]]`, or the single ECU of the `[topics]` section
+    pub ecus: Vec<EcuResolved>,
+}
+
+/// One ECU with its topics and commands.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EcuResolved {
+    /// Name/id of the ECU
+    pub ecuid: String,
+    /// open3e ECU address (e.g. `0x680`), sent as `addr` in generated commands
+    pub address: Option<String>,
+    /// Topic of the error history (DID 266)
     pub error: String,
+    /// Topic of the warning history (DID 264)
     pub warning: String,
+    /// Topic of the service history (DID 262)
     pub service: String,
+    /// Topic of the info history (DID 260)
     pub info: String,
+    /// Topic of the status history (DID 258)
     pub status: String,
-    pub ecuid: String,
-    pub systemid: String,
-    pub command_topic: String,
-    pub command_payload: String,
-    pub command_interval_secs: u64,
+    /// Topic of the list of the currently active errors
+    pub active_errors: String,
+    /// Commands polled for this ECU
+    pub commands: Vec<CommandResolved>,
+    /// Extra InfluxDB tags of this ECU (e.g. `location`)
+    pub static_tags: Vec<(String, String)>,
+}
+
+/// A command with its message and schedule.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommandResolved {
+    /// DIDs the command reads
+    pub dids: Vec<u16>,
+    /// Command message (JSON)
+    pub payload: String,
+    /// Interval of the command
+    pub interval_secs: u64,
+    /// Random delay of up to this many seconds for each run
+    pub jitter_secs: u64,
+}
+
+/// History DIDs and the open3e names of their topics.
+pub const HISTORY_DIDS: [(u16, &str); 5] = [
+    (266, "ErrorDtcHistory"),
+    (264, "WarningDtcHistory"),
+    (262, "ServiceDtcHistory"),
+    (260, "InfoDtcHistory"),
+    (258, "StatusDtcHistory"),
+];
+
+/// DID and open3e name of the list of the currently active errors.
+pub const ACTIVE_ERRORS_DID: (u16, &str) = (265, "ErrorDtcList");
+
+/// DIDs read when no command is configured: all history lists, and the active
+/// errors if `active_errors` is set.
+pub fn default_dids(active_errors: bool) -> Vec<u16> {
+    HISTORY_DIDS
+        .iter()
+        .map(|(did, _)| *did)
+        .chain(active_errors.then_some(ACTIVE_ERRORS_DID.0))
+        .collect()
+}
+
+impl TopicsResolved {
+    /// All history topics.
+    pub fn history_topics(&self) -> Vec<&str> {
+        self.ecus.iter().flat_map(|e| e.history_topics()).collect()
+    }
+
+    /// All topics ViLog subscribes to: the history topics and, if read, those of the active errors.
+    pub fn subscribed_topics(&self) -> Vec<&str> {
+        self.ecus
+            .iter()
+            .flat_map(|e| {
+                e.history_topics()
+                    .into_iter()
+                    .chain(self.active_errors.then_some(e.active_errors.as_str()))
+            })
+            .collect()
+    }
+
+    /// The ECU whose active errors are published on `topic` (none if they are not read).
+    pub fn ecu_for_active_errors(&self, topic: &str) -> Option<&EcuResolved> {
+        self.ecus
+            .iter()
+            .find(|e| self.active_errors && e.active_errors == topic)
+    }
+
+    /// Whether `topic` is the history topic of an ECU.
+    pub fn is_history_topic(&self, topic: &str) -> bool {
+        self.ecu_for_topic(topic).is_some()
+    }
+
+    /// The ECU with the history topic `topic`.
+    pub fn ecu_for_topic(&self, topic: &str) -> Option<&EcuResolved> {
+        self.ecus
+            .iter()
+            .find(|e| e.history_topics().contains(&topic))
+    }
+
+    /// Maps an open3e DID (e.g. 258 from a `258_1.json` capture) to the topic of the first ECU.
+    pub fn topic_for_did(&self, did: u16) -> Option<&str> {
+        self.ecus.first().and_then(|e| e.topic_for_did(did))
+    }
+}
+
+impl EcuResolved {
+    /// The history topics of the ECU, in the order of [`HISTORY_DIDS`].
+    pub fn history_topics(&self) -> [&str; 5] {
+        [
+            self.error.as_str(),
+            self.warning.as_str(),
+            self.service.as_str(),
+            self.info.as_str(),
+            self.status.as_str(),
+        ]
+    }
+
+    /// The topic of the history DID `did`, `None` for other DIDs.
+    pub fn topic_for_did(&self, did: u16) -> Option<&str> {
+        match did {
+            266 => Some(self.error.as_str()),
+            264 => Some(self.warning.as_str()),
+            262 => Some(self.service.as_str()),
+            260 => Some(self.info.as_str()),
+            258 => Some(self.status.as_str()),
+            _ => None,
+        }
+    }
+
+    /// The history DID published on `topic`.
+    pub fn did_for_topic(&self, topic: &str) -> Option<u16> {
+        HISTORY_DIDS
+            .iter()
+            .map(|(did, _)| *did)
+            .find(|did| self.topic_for_did(*did) == Some(topic))
+    }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// `[influxdb]`: where and how the events are written.
+#[derive(Deserialize, Clone, PartialEq)]
 pub struct InfluxConfig {
+    /// Write to InfluxDB
     pub enabled: Option<bool>,
+    /// `v1`, `v2` or `v3`
+    pub api_version: Option<String>,
+    /// Base URL of the server
     pub url: Option<String>,
+    /// v2: organization
     pub org: Option<String>,
+    /// v2: bucket
     pub bucket: Option<String>,
+    /// v2/v3: auth token
     pub token: Option<String>,
+    /// v1/v3: database
+    pub database: Option<String>,
+    /// v1: retention policy
+    pub retention_policy: Option<String>,
+    /// v1: user name
+    pub username: Option<String>,
+    /// v1: password
+    pub password: Option<String>,
+    /// v1: send the credentials as `u`/`p` parameters
+    pub credentials_in_query: Option<bool>,
+    /// Measurement name
     pub measurement: Option<String>,
+    /// HTTP request timeout
     pub timeout_secs: Option<u64>,
+    /// Lines after which a batch is sent
+    pub batch_size: Option<usize>,
+    /// Time after which a batch is sent
+    pub flush_interval_ms: Option<u64>,
+    /// Write requests running at the same time
+    pub max_in_flight: Option<usize>,
+    /// Compress the request body
+    pub gzip: Option<bool>,
+    /// Attributes written as tags
+    pub tags: Option<Vec<String>>,
+    /// Attributes written as fields
+    pub fields: Option<Vec<String>>,
+    /// Tags written with every line
+    pub static_tags: Option<BTreeMap<String, String>>,
 }
 
-#[derive(Clone, Debug)]
+/// Write API of the InfluxDB server.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InfluxApiVersion {
+    /// InfluxDB 1.x: `/write?db=&rp=`, basic auth or `u`/`p` parameters
+    V1,
+    /// InfluxDB 2.x: `/api/v2/write?org=&bucket=`, token auth
+    V2,
+    /// InfluxDB 3: `/api/v3/write_lp?db=`, bearer token auth
+    V3,
+}
+
+impl InfluxApiVersion {
+    /// Parses `v1`/`1`, `v2`/`2` or `v3`/`3`.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "v1" | "1" => Some(Self::V1),
+            "v2" | "2" => Some(Self::V2),
+            "v3" | "3" => Some(Self::V3),
+            _ => None,
+        }
+    }
+}
+
+/// The `[influxdb]` settings to run with.
+#[derive(Clone)]
 pub struct InfluxResolved {
+    /// Write to InfluxDB
     pub enabled: bool,
+    /// Write API of the server
+    pub api_version: InfluxApiVersion,
+    /// Base URL of the server
     pub url: String,
+    /// v2: organization
     pub org: String,
+    /// v2: bucket
     pub bucket: String,
+    /// v2/v3: auth token
     pub token: String,
+    /// v1/v3: database
+    pub database: String,
+    /// v1: retention policy; empty for the default one
+    pub retention_policy: String,
+    /// v1: user name
+    pub username: String,
+    /// v1: password
+    pub password: String,
+    /// v1: send the credentials as `u`/`p` parameters
+    pub credentials_in_query: bool,
+    /// Measurement name
     pub measurement: String,
+    /// HTTP request timeout
     pub timeout_secs: u64,
+    /// Lines after which a batch is sent
+    pub batch_size: usize,
+    /// Time after which a batch is sent
+    pub flush_interval_ms: u64,
+    /// Write requests running at the same time
+    pub max_in_flight: usize,
+    /// Compress the request body
+    pub gzip: bool,
+    /// Tags and fields of the lines
+    pub schema: InfluxSchema,
 }
 
-pub fn read_app_config() -> Option<AppConfig> {
-    use std::env;
+/// Shown instead of secrets in `Debug` output.
+const REDACTED: &str = "***";
 
-    // standard-path: ./vilog.toml; override with ENV `VILOG_CONFIG`
-    let path = env::var("VILOG_CONFIG").unwrap_or_else(|_| "vilog.toml".to_string());
+fn redact_opt(secret: &Option<String>) -> Option<&'static str> {
+    secret.as_ref().map(|_| REDACTED)
+}
 
-    let Ok(raw) = fs::read_to_string(&path) else {
-        log::warn!("Keine config file found at  '{}'; using defaults.", path);
-        return None;
-    };
+fn redact(secret: &str) -> &'static str {
+    if secret.is_empty() { "" } else { REDACTED }
+}
+
+impl fmt::Debug for MqttConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MqttConfig")
+            .field("client_id", &self.client_id)
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("username", &self.username)
+            .field("password", &redact_opt(&self.password))
+            .field("keep_alive_secs", &self.keep_alive_secs)
+            .field("protocol", &self.protocol)
+            .field("subscribe_qos", &self.subscribe_qos)
+            .field("command_qos", &self.command_qos)
+            .field("persistent_session", &self.persistent_session)
+            .field("session_expiry_secs", &self.session_expiry_secs)
+            .field("tls", &self.tls)
+            .field("ca_path", &self.ca_path)
+            .field("client_cert_path", &self.client_cert_path)
+            .field("client_key_path", &self.client_key_path)
+            .field("alpn", &self.alpn)
+            .field("accept_invalid_certs", &self.accept_invalid_certs)
+            .finish()
+    }
+}
+
+impl fmt::Debug for InfluxConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InfluxConfig")
+            .field("enabled", &self.enabled)
+            .field("api_version", &self.api_version)
+            .field("url", &self.url)
+            .field("org", &self.org)
+            .field("bucket", &self.bucket)
+            .field("token", &redact_opt(&self.token))
+            .field("database", &self.database)
+            .field("retention_policy", &self.retention_policy)
+            .field("username", &self.username)
+            .field("password", &redact_opt(&self.password))
+            .field("credentials_in_query", &self.credentials_in_query)
+            .field("measurement", &self.measurement)
+            .field("timeout_secs", &self.timeout_secs)
+            .field("batch_size", &self.batch_size)
+            .field("flush_interval_ms", &self.flush_interval_ms)
+            .field("max_in_flight", &self.max_in_flight)
+            .field("gzip", &self.gzip)
+            .field("tags", &self.tags)
+            .field("fields", &self.fields)
+            .field("static_tags", &self.static_tags)
+            .finish()
+    }
+}
+
+impl fmt::Debug for InfluxResolved {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InfluxResolved")
+            .field("enabled", &self.enabled)
+            .field("api_version", &self.api_version)
+            .field("url", &self.url)
+            .field("org", &self.org)
+            .field("bucket", &self.bucket)
+            .field("token", &redact(&self.token))
+            .field("database", &self.database)
+            .field("retention_policy", &self.retention_policy)
+            .field("username", &self.username)
+            .field("password", &redact(&self.password))
+            .field("credentials_in_query", &self.credentials_in_query)
+            .field("measurement", &self.measurement)
+            .field("timeout_secs", &self.timeout_secs)
+            .field("batch_size", &self.batch_size)
+            .field("flush_interval_ms", &self.flush_interval_ms)
+            .field("max_in_flight", &self.max_in_flight)
+            .field("gzip", &self.gzip)
+            .field("schema", &self.schema)
+            .finish()
+    }
+}
+
+/// `[capture]`: recording of the received MQTT messages.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct CaptureConfig {
+    /// Record the messages
+    pub enabled: Option<bool>,
+    /// Path of the capture file
+    pub path: Option<String>,
+    /// Size at which the file is rotated
+    pub max_bytes: Option<u64>,
+    /// Rotated files to keep
+    pub max_files: Option<usize>,
+}
+
+/// The `[capture]` settings to run with.
+#[derive(Clone, Debug)]
+pub struct CaptureResolved {
+    /// Record the messages
+    pub enabled: bool,
+    /// Path of the capture file
+    pub path: String,
+    /// Size at which the file is rotated
+    pub max_bytes: u64,
+    /// Rotated files to keep
+    pub max_files: usize,
+}
+
+/// `[store]`: local SQLite database of all emitted events (`vilog query`).
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct StoreConfig {
+    /// Store the events
+    pub enabled: Option<bool>,
+    /// Path of the database
+    pub path: Option<String>,
+    /// Days an event is kept
+    pub retention_days: Option<u32>,
+}
+
+/// The `[store]` settings to run with.
+#[derive(Clone, Debug)]
+pub struct StoreResolved {
+    /// Store the events
+    pub enabled: bool,
+    /// Path of the database
+    pub path: String,
+    /// Days events are kept after they were stored; 0 keeps them forever
+    pub retention_days: u32,
+}
+
+/// `[logfile]`: events written to a rotating file.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct LogFileConfig {
+    /// Write the events to the file
+    pub enabled: Option<bool>,
+    /// Path of the active file
+    pub path: Option<String>,
+    /// Line format, as in `[output]`
+    pub format: Option<String>,
+    /// Line template for `format = "template"`
+    pub template: Option<String>,
+    /// Size at which the file is rotated
+    pub max_bytes: Option<u64>,
+    /// Rotate at midnight
+    pub daily: Option<bool>,
+    /// Rotated files to keep
+    pub max_files: Option<usize>,
+    /// Compress the rotated files
+    pub gzip: Option<bool>,
+}
+
+/// The `[logfile]` settings to run with.
+#[derive(Clone, Debug)]
+pub struct LogFileResolved {
+    /// Write the events to the file
+    pub enabled: bool,
+    /// Path of the active file
+    pub path: String,
+    /// Line format
+    pub format: OutputFormat,
+    /// Rotate when the file gets bigger than this; 0 = no size limit
+    pub max_bytes: u64,
+    /// Rotate at midnight (local time)
+    pub daily: bool,
+    /// Rotated files to keep
+    pub max_files: usize,
+    /// Compress the rotated files
+    pub gzip: bool,
+}
+
+/// `[output]`: console output.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct OutputConfig {
+    /// `human`, `json`, `logfmt`, `csv` or `template`
+    pub format: Option<String>,
+    /// Line template for `format = "template"`
+    pub template: Option<String>,
+}
+
+/// The `[output]` settings to run with.
+#[derive(Clone, Debug)]
+pub struct OutputResolved {
+    /// Line format
+    pub format: OutputFormat,
+}
+
+/// `[time]`: correction of the device time.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct TimeConfig {
+    /// `device`, `timezone`, `offset` or `auto`
+    pub mode: Option<String>,
+    /// IANA timezone of the device
+    pub timezone: Option<String>,
+    /// Fixed correction
+    pub offset_secs: Option<i64>,
+    /// Rounding of the auto estimate
+    pub auto_round_secs: Option<i64>,
+}
+
+/// The `[time]` settings to run with.
+#[derive(Clone, Debug)]
+pub struct TimeResolved {
+    /// How the time of an entry is determined
+    pub mode: TimeMode,
+    /// Fixed correction, and the start value of `auto`
+    pub offset_secs: i64,
+}
+
+/// `[validation]`: entries with a wrong date/time.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ValidationConfig {
+    /// `mark`, `drop` or `correct`
+    pub policy: Option<String>,
+    /// Timestamps further ahead of the host clock are invalid
+    pub max_future_secs: Option<i64>,
+}
+
+/// The `[validation]` settings to run with.
+#[derive(Clone, Debug)]
+pub struct ValidationResolved {
+    /// What happens to invalid entries
+    pub policy: ValidationPolicy,
+    /// Timestamps further ahead of the host clock are invalid
+    pub max_future_secs: i64,
+}
+
+/// `[reload]`: reloading the configuration.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ReloadConfig {
+    /// Reload when the config file changes
+    pub watch: Option<bool>,
+    /// How often the config file is checked
+    pub watch_interval_secs: Option<u64>,
+}
+
+/// The `[reload]` settings to run with.
+#[derive(Clone, Debug)]
+pub struct ReloadResolved {
+    /// Reload when the config file changes
+    pub watch: bool,
+    /// How often the config file is checked
+    pub watch_interval_secs: u64,
+}
+
+/// `[systemd]`: service manager notifications and journald logging.
+#[derive(Debug, Default, Deserialize, Clone, PartialEq)]
+pub struct SystemdConfig {
+    /// Notify systemd (`Type=notify`)
+    pub notify: Option<bool>,
+    /// Log the events to journald
+    pub journal: Option<bool>,
+}
+
+/// The `[systemd]` settings to run with.
+#[derive(Clone, Debug)]
+pub struct SystemdResolved {
+    /// READY=1 and watchdog pings if started by systemd (`Type=notify`)
+    pub notify: bool,
+    /// Log the events to journald with `VILOG_*` fields
+    pub journal: bool,
+}
+
+/// `[state]`: dedup state kept across restarts.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct StateConfig {
+    /// Save and restore the state
+    pub enabled: Option<bool>,
+    /// Path of the state file
+    pub path: Option<String>,
+}
+
+/// The `[state]` settings to run with.
+#[derive(Clone, Debug)]
+pub struct StateResolved {
+    /// Save and restore the state
+    pub enabled: bool,
+    /// Path of the state file
+    pub path: String,
+}
+
+/// `[shutdown]`: what happens on SIGINT/SIGTERM.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ShutdownConfig {
+    /// How long pending writes may take
+    pub timeout_secs: Option<u64>,
+}
+
+/// The `[shutdown]` settings to run with.
+#[derive(Clone, Debug)]
+pub struct ShutdownResolved {
+    /// How long pending writes may take before ViLog exits anyway
+    pub timeout_secs: u64,
+}
+
+/// `[api]`: HTTP API with the events, ECUs and daemon status.
+#[derive(Deserialize, Clone, PartialEq)]
+pub struct ApiConfig {
+    /// Start the HTTP API
+    pub enabled: Option<bool>,
+    /// Address and port to listen on
+    pub listen: Option<String>,
+    /// Bearer token every request must send
+    pub token: Option<String>,
+    /// Events kept in memory
+    pub max_events: Option<usize>,
+}
+
+/// The `[api]` settings to run with.
+#[derive(Clone)]
+pub struct ApiResolved {
+    /// Start the HTTP API
+    pub enabled: bool,
+    /// Address and port to listen on
+    pub listen: String,
+    /// Bearer token every request must send; `None` allows all requests
+    pub token: Option<String>,
+    /// Number of recent events kept in memory for `GET /events`
+    pub max_events: usize,
+}
+
+impl fmt::Debug for ApiConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApiConfig")
+            .field("enabled", &self.enabled)
+            .field("listen", &self.listen)
+            .field("token", &redact_opt(&self.token))
+            .field("max_events", &self.max_events)
+            .finish()
+    }
+}
+
+impl fmt::Debug for ApiResolved {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApiResolved")
+            .field("enabled", &self.enabled)
+            .field("listen", &self.listen)
+            .field("token", &redact_opt(&self.token))
+            .field("max_events", &self.max_events)
+            .finish()
+    }
+}
+
+/// Path of the config file: `./vilog.toml`, override with ENV `VILOG_CONFIG`.
+pub fn config_path() -> String {
+    std::env::var("VILOG_CONFIG").unwrap_or_else(|_| "vilog.toml".to_string())
+}
+
+/// Strict mode: unknown keys are errors. On by default, `VILOG_STRICT=0` turns them
+/// into warnings.
+pub fn strict_mode() -> bool {
+    std::env::var("VILOG_STRICT")
+        .map(|v| !matches!(v.trim().to_ascii_lowercase().as_str(), "0" | "false" | "no"))
+        .unwrap_or(true)
+}
 
-    match toml::from_str::<AppConfig>(&raw) {
-        Ok(cfg) => Some(cfg),
-        Err(e) => {
-            log::warn!("error parsing '{}': {} — using defaults.", path, e);
+/// Reads the config file named by `VILOG_CONFIG` (default `vilog.toml`), see [`load_app_config`].
+pub fn read_app_config(strict: bool) -> Result<Option<AppConfig>, String> {
+    load_app_config(&config_path(), strict)
+}
+
+/// Reads the config file, applies the environment overrides and checks the result.
+///
+/// Returns `None` if there is neither a config file nor an override. Syntax and type
+/// errors and values that can not work are errors; unknown keys are errors in `strict`
+/// mode and warnings otherwise. Errors report the line and column in the file.
+pub fn load_app_config(path: &str, strict: bool) -> Result<Option<AppConfig>, String> {
+    let raw = match fs::read_to_string(path) {
+        Ok(raw) => Some(raw),
+        Err(_) => {
+            log::warn!("Keine config file found at  '{}'; using defaults.", path);
             None
         }
+    };
+
+    let mut table = match raw.as_deref().map(toml::from_str::<toml::Table>) {
+        None => toml::Table::new(),
+        Some(Ok(t)) => t,
+        Some(Err(e)) => return Err(format!("error parsing '{}': {}", path, e)),
+    };
+    // type errors with their position, before the overrides are mixed in
+    if let Some(raw) = raw.as_deref() {
+        toml::from_str::<AppConfig>(raw).map_err(|e| format!("error in '{}': {}", path, e))?;
+    }
+
+    let overridden = apply_env_overrides::<AppConfig, _>(&mut table, std::env::vars())
+        .map_err(|e| format!("error in environment override {}", e))?;
+    if !overridden.is_empty() {
+        log::info!(
+            "Config keys set from environment: {}",
+            overridden.join(", ")
+        );
+    }
+    if raw.is_none() && overridden.is_empty() {
+        return Ok(None);
+    }
+
+    let mut errors = Vec::new();
+    for key in unknown_keys(&table) {
+        let issue = ConfigIssue::new(raw.as_deref(), key, "unknown key");
+        if strict {
+            errors.push(issue);
+        } else {
+            log::warn!("'{}': {}", path, issue);
+        }
     }
+
+    let cfg = toml::Value::Table(table)
+        .try_into::<AppConfig>()
+        .map_err(|e| format!("error in environment override: {}", e))?;
+
+    errors.extend(
+        check_values(&cfg)
+            .into_iter()
+            .map(|(key, message)| ConfigIssue::new(raw.as_deref(), key, message)),
+    );
+    if !errors.is_empty() {
+        let lines: Vec<String> = errors.iter().map(|e| format!("  {}", e)).collect();
+        return Err(format!(
+            "invalid configuration '{}':\n{}",
+            path,
+            lines.join("\n")
+        ));
+    }
+
+    Ok(Some(cfg))
 }
 
-pub fn create_mqtt_options(cfg: Option<&MqttConfig>) -> MqttOptions {
+/// Broker connection settings, shared by the MQTT v5 and v3.1.1 clients.
+pub struct MqttResolved {
+    /// Client id
+    pub client_id: String,
+    /// Host name, or the full `ws://`/`wss://` URL for WebSocket transports
+    pub host: String,
+    /// Broker port
+    pub port: u16,
+    /// User name
+    pub username: String,
+    /// Password
+    pub password: String,
+    /// Keep-alive of the connection
+    pub keep_alive_secs: u64,
+    /// MQTT protocol version
+    pub protocol: MqttProtocol,
+    /// QoS of the subscriptions
+    pub subscribe_qos: QoS,
+    /// QoS of the command messages
+    pub command_qos: QoS,
+    /// Keep the session (and queued QoS 1/2 messages) on the broker while disconnected
+    pub persistent_session: bool,
+    /// How long the broker keeps a persistent session (v5 only)
+    pub session_expiry_secs: u32,
+    /// TCP, TLS or WebSocket transport
+    pub transport: Transport,
+}
+
+impl fmt::Debug for MqttResolved {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MqttResolved")
+            .field("client_id", &self.client_id)
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("username", &self.username)
+            .field("password", &redact(&self.password))
+            .field("keep_alive_secs", &self.keep_alive_secs)
+            .field("protocol", &self.protocol)
+            .field("subscribe_qos", &self.subscribe_qos)
+            .field("command_qos", &self.command_qos)
+            .field("persistent_session", &self.persistent_session)
+            .field("session_expiry_secs", &self.session_expiry_secs)
+            .field("transport", &transport_name(&self.transport))
+            .finish()
+    }
+}
+
+fn transport_name(transport: &Transport) -> &'static str {
+    match transport {
+        Transport::Tcp => "tcp",
+        Transport::Tls(_) => "tls",
+        Transport::Ws => "ws",
+        Transport::Wss(_) => "wss",
+        #[allow(unreachable_patterns)]
+        _ => "other",
+    }
+}
+
+/// The `[mqtt]` section with its defaults applied; fails if the TLS files can not be read.
+pub fn resolve_mqtt(cfg: Option<&MqttConfig>) -> Result<MqttResolved, Box<dyn Error>> {
     let default_client_id = "vilogger".to_string();
     let default_host = "127.0.0.1".to_string();
     let default_port: u16 = 1883;
+    let default_tls_port: u16 = 8883;
     let default_username = "vilogger".to_string();
     let default_password = "".to_string();
     let default_keep_alive_secs: u64 = 30;
+    let default_subscribe_qos = QoS::AtMostOnce;
+    let default_command_qos = QoS::ExactlyOnce;
+    let default_session_expiry_secs: u32 = 3600;
 
     let client_id = cfg
         .and_then(|c| c.client_id.clone())
         .unwrap_or(default_client_id);
     let host = cfg.and_then(|c| c.host.clone()).unwrap_or(default_host);
-    let port = cfg.and_then(|c| c.port).unwrap_or(default_port);
 
-    let mut mqtt_options = MqttOptions::new(client_id, host, port);
+    let transport = create_transport(cfg, &host)?;
+    // ignored for WebSockets, the port is part of the URL there
+    let port = cfg
+        .and_then(|c| c.port)
+        .unwrap_or(if matches!(transport, Transport::Tls(_)) {
+            default_tls_port
+        } else {
+            default_port
+        });
 
     let username = cfg
         .and_then(|c| c.username.clone())
@@ -112,80 +947,581 @@ pub fn create_mqtt_options(cfg: Option<&MqttConfig>) -> MqttOptions {
     let password = cfg
         .and_then(|c| c.password.clone())
         .unwrap_or(default_password);
-    mqtt_options.set_credentials(username, password);
-
     let keep_alive_secs = cfg
         .and_then(|c| c.keep_alive_secs)
         .unwrap_or(default_keep_alive_secs);
-    mqtt_options.set_keep_alive(Duration::from_secs(keep_alive_secs));
 
-    mqtt_options
+    let protocol = match cfg.and_then(|c| c.protocol.as_deref()) {
+        None => MqttProtocol::V5,
+        Some(p) => MqttProtocol::parse(p).unwrap_or_else(|| {
+            log::warn!("unknown mqtt protocol '{}'; using v5.", p);
+            MqttProtocol::V5
+        }),
+    };
+
+    let subscribe_qos = resolve_qos(
+        "subscribe_qos",
+        cfg.and_then(|c| c.subscribe_qos),
+        default_subscribe_qos,
+    );
+    let command_qos = resolve_qos(
+        "command_qos",
+        cfg.and_then(|c| c.command_qos),
+        default_command_qos,
+    );
+    let persistent_session = cfg.and_then(|c| c.persistent_session).unwrap_or(false);
+    let session_expiry_secs = cfg
+        .and_then(|c| c.session_expiry_secs)
+        .unwrap_or(default_session_expiry_secs);
+    if persistent_session && subscribe_qos == QoS::AtMostOnce {
+        log::warn!(
+            "persistent_session with subscribe_qos = 0: the broker does not queue QoS 0 messages"
+        );
+    }
+
+    Ok(MqttResolved {
+        client_id,
+        host,
+        port,
+        username,
+        password,
+        keep_alive_secs,
+        protocol,
+        subscribe_qos,
+        command_qos,
+        persistent_session,
+        session_expiry_secs,
+        transport,
+    })
+}
+
+fn resolve_qos(key: &str, value: Option<u8>, default: QoS) -> QoS {
+    match value {
+        None => default,
+        Some(v) => mqtt::parse_qos(v).unwrap_or_else(|| {
+            log::warn!("invalid mqtt {} {}; using {:?}.", key, v, default);
+            default
+        }),
+    }
 }
 
-pub fn resolve_topics(cfg: Option<&TopicsConfig>) -> TopicsResolved {
-    let defaults = TopicsResolved {
-        error: "open3e/680_266_ErrorDtcHistory".to_string(),
-        warning: "open3e/680_264_WarningDtcHistory".to_string(),
-        service: "open3e/680_262_ServiceDtcHistory".to_string(),
-        info: "open3e/680_260_InfoDtcHistory".to_string(),
-        status: "open3e/680_258_StatusDtcHistory".to_string(),
-        ecuid: "HPMU".to_string(),
-        systemid: "250A".to_string(),
-        command_topic: "open3e/cmnd".to_string(),
-        command_payload: "{\"mode\": \"read-json\", \"data\":[258,260,262,264,266]}".to_string(),
-        command_interval_secs: 60,
+/// Fills in the defaults of `[topics]` and the `[[ecus]]`; without `[[ecus]]` the `[topics]` section is the only ECU.
+///
+/// `active_errors` (the HTTP API is enabled) adds the active errors (DID 265) to the
+/// default commands and the subscriptions.
+pub fn resolve_topics(
+    cfg: Option<&TopicsConfig>,
+    ecus: Option<&[EcuConfig]>,
+    active_errors: bool,
+) -> TopicsResolved {
+    let default_ecuid = "HPMU".to_string();
+    let default_systemid = "250A".to_string();
+    let default_command_topic = "open3e/cmnd".to_string();
+    let default_command_payload = if active_errors {
+        "{\"mode\": \"read-json\", \"data\":[258,260,262,264,265,266]}".to_string()
+    } else {
+        "{\"mode\": \"read-json\", \"data\":[258,260,262,264,266]}".to_string()
+    };
+    let default_command_interval_secs: u64 = 60;
+    let default_stagger_secs: u64 = 5;
+    let default_gap_interval_secs: u64 = 10;
+    let default_gap_quiet_polls: u32 = 3;
+
+    let interval_secs = cfg
+        .and_then(|c| c.command_interval_secs)
+        .unwrap_or(default_command_interval_secs);
+    // history topics of [topics], also the fallback for [[ecus]] without address
+    let topic = |did: u16, value: Option<&String>| {
+        value.cloned().unwrap_or_else(|| history_topic("680", did))
+    };
+    let base = EcuResolved {
+        ecuid: cfg.and_then(|c| c.ecuid.clone()).unwrap_or(default_ecuid),
+        address: None,
+        error: topic(266, cfg.and_then(|c| c.error.as_ref())),
+        warning: topic(264, cfg.and_then(|c| c.warning.as_ref())),
+        service: topic(262, cfg.and_then(|c| c.service.as_ref())),
+        info: topic(260, cfg.and_then(|c| c.info.as_ref())),
+        status: topic(258, cfg.and_then(|c| c.status.as_ref())),
+        active_errors: topic(
+            ACTIVE_ERRORS_DID.0,
+            cfg.and_then(|c| c.active_errors.as_ref()),
+        ),
+        commands: Vec::new(),
+        static_tags: Vec::new(),
+    };
+
+    let ecus = match ecus {
+        Some(ecus) if !ecus.is_empty() => ecus
+            .iter()
+            .enumerate()
+            .map(|(i, ecu)| resolve_ecu(i, ecu, &base, interval_secs, active_errors))
+            .collect(),
+        _ => {
+            let payload = cfg
+                .and_then(|c| c.command_payload.clone())
+                .unwrap_or(default_command_payload);
+            vec![EcuResolved {
+                commands: vec![CommandResolved {
+                    dids: dids_of_payload(&payload),
+                    payload,
+                    interval_secs,
+                    jitter_secs: 0,
+                }],
+                ..base
+            }]
+        }
     };
 
     TopicsResolved {
-        error: cfg.and_then(|c| c.error.clone()).unwrap_or(defaults.error),
-        warning: cfg
-            .and_then(|c| c.warning.clone())
-            .unwrap_or(defaults.warning),
-        service: cfg
-            .and_then(|c| c.service.clone())
-            .unwrap_or(defaults.service),
-        info: cfg.and_then(|c| c.info.clone()).unwrap_or(defaults.info),
-        status: cfg
-            .and_then(|c| c.status.clone())
-            .unwrap_or(defaults.status),
-        ecuid: cfg.and_then(|c| c.ecuid.clone()).unwrap_or(defaults.ecuid),
         systemid: cfg
             .and_then(|c| c.systemid.clone())
-            .unwrap_or(defaults.systemid),
+            .unwrap_or(default_systemid),
         command_topic: cfg
             .and_then(|c| c.command_topic.clone())
-            .unwrap_or(defaults.command_topic),
-        command_payload: cfg
-            .and_then(|c| c.command_payload.clone())
-            .unwrap_or(defaults.command_payload),
-        command_interval_secs: cfg
-            .and_then(|c| c.command_interval_secs)
-            .unwrap_or(defaults.command_interval_secs),
+            .unwrap_or(default_command_topic),
+        stagger_secs: cfg
+            .and_then(|c| c.stagger_secs)
+            .unwrap_or(default_stagger_secs),
+        gap_interval_secs: cfg
+            .and_then(|c| c.gap_interval_secs)
+            .unwrap_or(default_gap_interval_secs),
+        gap_quiet_polls: cfg
+            .and_then(|c| c.gap_quiet_polls)
+            .unwrap_or(default_gap_quiet_polls),
+        active_errors,
+        ecus,
+    }
+}
+
+fn resolve_ecu(
+    index: usize,
+    cfg: &EcuConfig,
+    base: &EcuResolved,
+    interval_secs: u64,
+    active_errors: bool,
+) -> EcuResolved {
+    // "0x680" -> "680", as in the open3e topic names
+    let hex = cfg.address.as_deref().map(|a| {
+        let a = a.trim();
+        a.strip_prefix("0x")
+            .or_else(|| a.strip_prefix("0X"))
+            .unwrap_or(a)
+            .to_string()
+    });
+    let topic = |did: u16, value: &Option<String>, fallback: &str| match (value, &hex) {
+        (Some(t), _) => t.clone(),
+        (None, Some(hex)) => history_topic(hex, did),
+        (None, None) => fallback.to_string(),
+    };
+
+    // without [[ecus.commands]] all history DIDs (and the active errors) are read together
+    let read_all = [CommandConfig {
+        dids: None,
+        payload: None,
+        interval_secs: None,
+        jitter_secs: None,
+    }];
+    let commands = cfg
+        .commands
+        .as_deref()
+        .unwrap_or(&read_all)
+        .iter()
+        .map(|c| {
+            let dids = c.dids.clone().unwrap_or_else(|| match &c.payload {
+                Some(payload) => dids_of_payload(payload),
+                None => default_dids(active_errors),
+            });
+            CommandResolved {
+                payload: c
+                    .payload
+                    .clone()
+                    .unwrap_or_else(|| read_command(&dids, &cfg.address)),
+                dids,
+                interval_secs: c.interval_secs.unwrap_or(interval_secs),
+                jitter_secs: c.jitter_secs.unwrap_or(0),
+            }
+        })
+        .collect();
+
+    EcuResolved {
+        ecuid: cfg
+            .ecuid
+            .clone()
+            .or_else(|| hex.as_ref().map(|h| h.to_ascii_uppercase()))
+            .unwrap_or_else(|| format!("ECU{}", index + 1)),
+        address: cfg.address.clone(),
+        error: topic(266, &cfg.error, &base.error),
+        warning: topic(264, &cfg.warning, &base.warning),
+        service: topic(262, &cfg.service, &base.service),
+        info: topic(260, &cfg.info, &base.info),
+        status: topic(258, &cfg.status, &base.status),
+        active_errors: topic(ACTIVE_ERRORS_DID.0, &cfg.active_errors, &base.active_errors),
+        commands,
+        static_tags: cfg
+            .static_tags
+            .clone()
+            .map(|t| t.into_iter().collect())
+            .unwrap_or_default(),
+    }
+}
+
+/// open3e topic of a history DID, e.g. `open3e/680_266_ErrorDtcHistory`, or of the active errors.
+fn history_topic(hex_address: &str, did: u16) -> String {
+    let name = HISTORY_DIDS
+        .iter()
+        .chain([&ACTIVE_ERRORS_DID])
+        .find(|(d, _)| *d == did)
+        .map_or("", |(_, name)| name);
+    format!("open3e/{}_{}_{}", hex_address, did, name)
+}
+
+/// open3e command that reads `dids` as JSON, from the ECU at `address` if set.
+fn read_command(dids: &[u16], address: &Option<String>) -> String {
+    let mut command = serde_json::json!({ "mode": "read-json", "data": dids });
+    if let Some(address) = address {
+        command["addr"] = serde_json::Value::String(address.clone());
     }
+    command.to_string()
 }
 
+/// The DIDs in the `data` list of an open3e command.
+fn dids_of_payload(payload: &str) -> Vec<u16> {
+    serde_json::from_str::<serde_json::Value>(payload)
+        .ok()
+        .and_then(|v| {
+            v["data"].as_array().map(|data| {
+                data.iter()
+                    .filter_map(|d| d.as_u64().and_then(|d| u16::try_from(d).ok()))
+                    .collect()
+            })
+        })
+        .unwrap_or_default()
+}
+
+/// Fills in the defaults of `[influxdb]`.
 pub fn resolve_influx(cfg: Option<&InfluxConfig>) -> InfluxResolved {
     let defaults = InfluxResolved {
         enabled: false,
+        api_version: InfluxApiVersion::V2,
         url: "http://127.0.0.1:8086".to_string(),
         org: "my-org".to_string(),
         bucket: "vilog".to_string(),
         token: String::new(),
+        database: "vilog".to_string(),
+        retention_policy: String::new(),
+        username: String::new(),
+        password: String::new(),
+        credentials_in_query: false,
         measurement: "syslog".to_string(),
         timeout_secs: 5,
+        batch_size: 5000,
+        flush_interval_ms: 1000,
+        max_in_flight: 2,
+        gzip: false,
+        schema: InfluxSchema::default(),
+    };
+
+    let api_version = match cfg.and_then(|c| c.api_version.as_deref()) {
+        None => defaults.api_version,
+        Some(v) => InfluxApiVersion::parse(v).unwrap_or_else(|| {
+            log::warn!("unknown influxdb api_version '{}'; using v2.", v);
+            defaults.api_version
+        }),
     };
 
     InfluxResolved {
         enabled: cfg.and_then(|c| c.enabled).unwrap_or(defaults.enabled),
+        api_version,
         url: cfg.and_then(|c| c.url.clone()).unwrap_or(defaults.url),
         org: cfg.and_then(|c| c.org.clone()).unwrap_or(defaults.org),
-        bucket: cfg.and_then(|c| c.bucket.clone()).unwrap_or(defaults.bucket),
+        bucket: cfg
+            .and_then(|c| c.bucket.clone())
+            .unwrap_or(defaults.bucket),
         token: cfg.and_then(|c| c.token.clone()).unwrap_or(defaults.token),
+        database: cfg
+            .and_then(|c| c.database.clone())
+            .unwrap_or(defaults.database),
+        retention_policy: cfg
+            .and_then(|c| c.retention_policy.clone())
+            .unwrap_or(defaults.retention_policy),
+        username: cfg
+            .and_then(|c| c.username.clone())
+            .unwrap_or(defaults.username),
+        password: cfg
+            .and_then(|c| c.password.clone())
+            .unwrap_or(defaults.password),
+        credentials_in_query: cfg
+            .and_then(|c| c.credentials_in_query)
+            .unwrap_or(defaults.credentials_in_query),
         measurement: cfg
             .and_then(|c| c.measurement.clone())
             .unwrap_or(defaults.measurement),
         timeout_secs: cfg
             .and_then(|c| c.timeout_secs)
             .unwrap_or(defaults.timeout_secs),
+        batch_size: cfg
+            .and_then(|c| c.batch_size)
+            .unwrap_or(defaults.batch_size),
+        flush_interval_ms: cfg
+            .and_then(|c| c.flush_interval_ms)
+            .unwrap_or(defaults.flush_interval_ms),
+        max_in_flight: cfg
+            .and_then(|c| c.max_in_flight)
+            .unwrap_or(defaults.max_in_flight),
+        gzip: cfg.and_then(|c| c.gzip).unwrap_or(defaults.gzip),
+        schema: resolve_influx_schema(cfg, defaults.schema),
+    }
+}
+
+/// Tags and fields of the InfluxDB lines. Unknown attributes are skipped, an attribute
+/// that is a tag is not written as field again.
+fn resolve_influx_schema(cfg: Option<&InfluxConfig>, defaults: InfluxSchema) -> InfluxSchema {
+    let attrs = |names: &[String], key: &str| -> Vec<InfluxAttr> {
+        names
+            .iter()
+            .filter_map(|name| {
+                let attr = InfluxAttr::parse(name);
+                if attr.is_none() {
+                    log::warn!("unknown influxdb {} attribute '{}'; skipped.", key, name);
+                }
+                attr
+            })
+            .collect()
+    };
+
+    let tags = match cfg.and_then(|c| c.tags.as_deref()) {
+        Some(names) => attrs(names, "tags"),
+        None => defaults.tags,
+    };
+    let mut fields = match cfg.and_then(|c| c.fields.as_deref()) {
+        Some(names) => attrs(names, "fields"),
+        None => defaults.fields,
+    };
+    fields.retain(|f| {
+        let tag = tags.contains(f);
+        if tag {
+            log::warn!(
+                "influxdb field '{}' is a tag already; skipping it.",
+                f.name()
+            );
+        }
+        !tag
+    });
+    // a line needs at least one field
+    if fields.iter().all(|f| *f == InfluxAttr::Suspect) {
+        log::warn!("influxdb fields has no attribute besides suspect; adding event_id.");
+        fields.insert(0, InfluxAttr::EventId);
+    }
+
+    InfluxSchema {
+        tags,
+        fields,
+        static_tags: cfg
+            .and_then(|c| c.static_tags.clone())
+            .map(|t| t.into_iter().collect())
+            .unwrap_or(defaults.static_tags),
+    }
+}
+
+/// Fills in the defaults of `[capture]`.
+pub fn resolve_capture(cfg: Option<&CaptureConfig>) -> CaptureResolved {
+    let defaults = CaptureResolved {
+        enabled: false,
+        path: "vilog-capture.jsonl".to_string(),
+        max_bytes: 10 * 1024 * 1024,
+        max_files: 5,
+    };
+
+    CaptureResolved {
+        enabled: cfg.and_then(|c| c.enabled).unwrap_or(defaults.enabled),
+        path: cfg.and_then(|c| c.path.clone()).unwrap_or(defaults.path),
+        max_bytes: cfg.and_then(|c| c.max_bytes).unwrap_or(defaults.max_bytes),
+        max_files: cfg.and_then(|c| c.max_files).unwrap_or(defaults.max_files),
+    }
+}
+
+/// Fills in the defaults of `[store]`.
+pub fn resolve_store(cfg: Option<&StoreConfig>) -> StoreResolved {
+    let defaults = StoreResolved {
+        enabled: false,
+        path: "vilog.db".to_string(),
+        retention_days: 365,
+    };
+
+    StoreResolved {
+        enabled: cfg.and_then(|c| c.enabled).unwrap_or(defaults.enabled),
+        path: cfg.and_then(|c| c.path.clone()).unwrap_or(defaults.path),
+        retention_days: cfg
+            .and_then(|c| c.retention_days)
+            .unwrap_or(defaults.retention_days),
+    }
+}
+
+/// Fills in the defaults of `[logfile]`.
+pub fn resolve_logfile(cfg: Option<&LogFileConfig>) -> LogFileResolved {
+    let defaults = LogFileResolved {
+        enabled: false,
+        path: "vilog-events.log".to_string(),
+        format: OutputFormat::Json,
+        max_bytes: 10 * 1024 * 1024,
+        daily: false,
+        max_files: 7,
+        gzip: false,
+    };
+
+    let format = match cfg.and_then(|c| c.format.as_deref()) {
+        None => defaults.format,
+        Some(f) => {
+            OutputFormat::parse(f, cfg.and_then(|c| c.template.as_deref())).unwrap_or_else(|e| {
+                log::warn!("invalid logfile format: {}; using json.", e);
+                OutputFormat::Json
+            })
+        }
+    };
+
+    LogFileResolved {
+        enabled: cfg.and_then(|c| c.enabled).unwrap_or(defaults.enabled),
+        path: cfg.and_then(|c| c.path.clone()).unwrap_or(defaults.path),
+        format,
+        max_bytes: cfg.and_then(|c| c.max_bytes).unwrap_or(defaults.max_bytes),
+        daily: cfg.and_then(|c| c.daily).unwrap_or(defaults.daily),
+        max_files: cfg.and_then(|c| c.max_files).unwrap_or(defaults.max_files),
+        gzip: cfg.and_then(|c| c.gzip).unwrap_or(defaults.gzip),
+    }
+}
+
+/// Fills in the defaults of `[output]`.
+pub fn resolve_output(cfg: Option<&OutputConfig>) -> OutputResolved {
+    let format = match cfg.and_then(|c| c.format.as_deref()) {
+        None => OutputFormat::Human,
+        Some(f) => {
+            OutputFormat::parse(f, cfg.and_then(|c| c.template.as_deref())).unwrap_or_else(|e| {
+                log::warn!("invalid output format: {}; using human.", e);
+                OutputFormat::Human
+            })
+        }
+    };
+
+    OutputResolved { format }
+}
+
+/// Fills in the defaults of `[time]`.
+pub fn resolve_time(cfg: Option<&TimeConfig>) -> TimeResolved {
+    let default_auto_round_secs = 900;
+
+    let auto_round_secs = cfg
+        .and_then(|c| c.auto_round_secs)
+        .unwrap_or(default_auto_round_secs);
+    let mode = match cfg.and_then(|c| c.mode.as_deref()) {
+        None => TimeMode::Device,
+        Some(m) => TimeMode::parse(m, cfg.and_then(|c| c.timezone.as_deref()), auto_round_secs)
+            .unwrap_or_else(|e| {
+                log::warn!("invalid time config: {}; using device timestamps.", e);
+                TimeMode::Device
+            }),
+    };
+
+    TimeResolved {
+        mode,
+        offset_secs: cfg.and_then(|c| c.offset_secs).unwrap_or(0),
+    }
+}
+
+/// Fills in the defaults of `[validation]`.
+pub fn resolve_validation(cfg: Option<&ValidationConfig>) -> ValidationResolved {
+    let defaults = ValidationResolved {
+        policy: ValidationPolicy::Mark,
+        max_future_secs: 86400,
+    };
+
+    let policy = match cfg.and_then(|c| c.policy.as_deref()) {
+        None => defaults.policy,
+        Some(p) => ValidationPolicy::parse(p).unwrap_or_else(|| {
+            log::warn!("unknown validation policy '{}'; using mark.", p);
+            defaults.policy
+        }),
+    };
+
+    ValidationResolved {
+        policy,
+        max_future_secs: cfg
+            .and_then(|c| c.max_future_secs)
+            .unwrap_or(defaults.max_future_secs),
+    }
+}
+
+/// Fills in the defaults of `[reload]`.
+pub fn resolve_reload(cfg: Option<&ReloadConfig>) -> ReloadResolved {
+    let defaults = ReloadResolved {
+        watch: false,
+        watch_interval_secs: 5,
+    };
+
+    ReloadResolved {
+        watch: cfg.and_then(|c| c.watch).unwrap_or(defaults.watch),
+        watch_interval_secs: cfg
+            .and_then(|c| c.watch_interval_secs)
+            .unwrap_or(defaults.watch_interval_secs),
+    }
+}
+
+/// Fills in the defaults of `[systemd]`.
+pub fn resolve_systemd(cfg: Option<&SystemdConfig>) -> SystemdResolved {
+    let defaults = SystemdResolved {
+        notify: true,
+        journal: false,
+    };
+
+    SystemdResolved {
+        notify: cfg.and_then(|c| c.notify).unwrap_or(defaults.notify),
+        journal: cfg.and_then(|c| c.journal).unwrap_or(defaults.journal),
+    }
+}
+
+/// Fills in the defaults of `[state]`.
+pub fn resolve_state(cfg: Option<&StateConfig>) -> StateResolved {
+    let defaults = StateResolved {
+        enabled: false,
+        path: "vilog-state.json".to_string(),
+    };
+
+    StateResolved {
+        enabled: cfg.and_then(|c| c.enabled).unwrap_or(defaults.enabled),
+        path: cfg.and_then(|c| c.path.clone()).unwrap_or(defaults.path),
+    }
+}
+
+/// Fills in the defaults of `[shutdown]`.
+pub fn resolve_shutdown(cfg: Option<&ShutdownConfig>) -> ShutdownResolved {
+    let defaults = ShutdownResolved { timeout_secs: 10 };
+
+    ShutdownResolved {
+        timeout_secs: cfg
+            .and_then(|c| c.timeout_secs)
+            .unwrap_or(defaults.timeout_secs),
+    }
+}
+
+/// Fills in the defaults of `[api]`.
+pub fn resolve_api(cfg: Option<&ApiConfig>) -> ApiResolved {
+    let defaults = ApiResolved {
+        enabled: false,
+        listen: "127.0.0.1:8080".to_string(),
+        token: None,
+        max_events: 1000,
+    };
+
+    ApiResolved {
+        enabled: cfg.and_then(|c| c.enabled).unwrap_or(defaults.enabled),
+        listen: cfg
+            .and_then(|c| c.listen.clone())
+            .unwrap_or(defaults.listen),
+        token: cfg
+            .and_then(|c| c.token.clone())
+            .filter(|t| !t.is_empty())
+            .or(defaults.token),
+        max_events: cfg
+            .and_then(|c| c.max_events)
+            .unwrap_or(defaults.max_events),
     }
 }
@@ -0,0 +1,371 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::net::SocketAddr;
+
+use serde_ignored::Path;
+use toml::de::{DeTable, DeValue};
+
+use crate::clock::TimeMode;
+use crate::config::{AppConfig, InfluxApiVersion, resolve_api, resolve_topics};
+use crate::format::OutputFormat;
+use crate::influxdb::{InfluxAttr, InfluxSchema};
+use crate::mqtt::{MqttProtocol, parse_qos};
+use crate::validate::ValidationPolicy;
+
+/// A problem in the configuration, with its position in the config file if known.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ConfigIssue {
+    /// Dotted key, e.g. `mqtt.port`
+    pub key: String,
+    /// Line and column (1-based) of the key in the config file
+    pub position: Option<(usize, usize)>,
+    /// What is wrong with the value
+    pub message: String,
+}
+
+impl ConfigIssue {
+    /// Creates the issue and looks up the position of `key` in `raw` (the config file).
+    pub fn new(raw: Option<&str>, key: String, message: impl Into<String>) -> Self {
+        let position = raw.and_then(|raw| key_position(raw, &key));
+        Self {
+            key,
+            position,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.position {
+            Some((line, column)) => write!(
+                f,
+                "line {}, column {}: {}: {}",
+                line, column, self.key, self.message
+            ),
+            None => write!(f, "{}: {}", self.key, self.message),
+        }
+    }
+}
+
+/// Keys that are not part of [`AppConfig`] (typos, removed settings).
+///
+/// Type errors are not reported here, they come from the normal deserialization.
+pub fn unknown_keys(table: &toml::Table) -> Vec<String> {
+    let mut keys = Vec::new();
+    let _: Result<AppConfig, _> =
+        serde_ignored::deserialize(toml::Value::Table(table.clone()), |path| {
+            keys.push(path_segments(&path).join("."));
+        });
+    keys
+}
+
+fn path_segments(path: &Path) -> Vec<String> {
+    let (parent, segment) = match path {
+        Path::Root => return Vec::new(),
+        Path::Seq { parent, index } => (parent, Some(index.to_string())),
+        Path::Map { parent, key } => (parent, Some(key.clone())),
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => (parent, None),
+    };
+    let mut segments = path_segments(parent);
+    segments.extend(segment);
+    segments
+}
+
+/// Checks values that are well-formed TOML but can not work.
+pub fn check_values(cfg: &AppConfig) -> Vec<(String, String)> {
+    let mut issues = Vec::new();
+    let mut issue = |key: &str, message: String| issues.push((key.to_string(), message));
+
+    if let Some(mqtt) = &cfg.mqtt {
+        if let Some(p) = &mqtt.protocol
+            && MqttProtocol::parse(p).is_none()
+        {
+            issue("mqtt.protocol", format!("unknown protocol '{}'", p));
+        }
+        for (key, qos) in [
+            ("mqtt.subscribe_qos", mqtt.subscribe_qos),
+            ("mqtt.command_qos", mqtt.command_qos),
+        ] {
+            if let Some(q) = qos
+                && parse_qos(q).is_none()
+            {
+                issue(key, format!("QoS must be 0, 1 or 2, not {}", q));
+            }
+        }
+    }
+
+    if let Some(topics) = &cfg.topics {
+        if let Some(payload) = &topics.command_payload
+            && let Err(e) = serde_json::from_str::<serde_json::Value>(payload)
+        {
+            issue("topics.command_payload", format!("invalid JSON: {}", e));
+        }
+        for (key, value) in [
+            ("topics.command_interval_secs", topics.command_interval_secs),
+            ("topics.gap_interval_secs", topics.gap_interval_secs),
+            (
+                "topics.gap_quiet_polls",
+                topics.gap_quiet_polls.map(u64::from),
+            ),
+        ] {
+            if value == Some(0) {
+                issue(key, "must be above zero".into());
+            }
+        }
+    }
+
+    for (i, ecu) in cfg.ecus.iter().flatten().enumerate() {
+        for (j, command) in ecu.commands.iter().flatten().enumerate() {
+            let key = |name: &str| format!("ecus.{}.commands.{}.{}", i, j, name);
+            if let Some(payload) = &command.payload
+                && let Err(e) = serde_json::from_str::<serde_json::Value>(payload)
+            {
+                issue(&key("payload"), format!("invalid JSON: {}", e));
+            }
+            if command.dids.as_ref().is_some_and(|d| d.is_empty()) {
+                issue(&key("dids"), "must not be empty".into());
+            }
+            if command.interval_secs == Some(0) {
+                issue(&key("interval_secs"), "must be above zero".into());
+            }
+        }
+    }
+    let topics = resolve_topics(
+        cfg.topics.as_ref(),
+        cfg.ecus.as_deref(),
+        resolve_api(cfg.api.as_ref()).enabled,
+    );
+    let mut seen = HashSet::new();
+    for (i, ecu) in topics.ecus.iter().enumerate() {
+        for topic in ecu.history_topics() {
+            if !seen.insert(topic) {
+                issue(
+                    &format!("ecus.{}", i),
+                    format!("history topic '{}' is used by more than one ECU", topic),
+                );
+            }
+        }
+        if topics.active_errors && !seen.insert(&ecu.active_errors) {
+            issue(
+                &format!("ecus.{}.active_errors", i),
+                format!("topic '{}' is used more than once", ecu.active_errors),
+            );
+        }
+    }
+
+    let default_tags = InfluxSchema::default().tags;
+    let tag_names: Vec<&str> = match cfg.influxdb.as_ref().and_then(|i| i.tags.as_ref()) {
+        Some(tags) => tags.iter().map(|t| t.trim()).collect(),
+        None => default_tags.iter().map(|a| a.name()).collect(),
+    };
+    if let Some(influx) = &cfg.influxdb {
+        if let Some(v) = &influx.api_version
+            && InfluxApiVersion::parse(v).is_none()
+        {
+            issue(
+                "influxdb.api_version",
+                format!("unknown api_version '{}'", v),
+            );
+        }
+        if let Some(url) = &influx.url {
+            match reqwest::Url::parse(url) {
+                Ok(u) if matches!(u.scheme(), "http" | "https") => {}
+                Ok(u) => issue(
+                    "influxdb.url",
+                    format!("unsupported scheme '{}', use http or https", u.scheme()),
+                ),
+                Err(e) => issue("influxdb.url", format!("invalid URL '{}': {}", url, e)),
+            }
+        }
+        for (key, value) in [
+            ("influxdb.timeout_secs", influx.timeout_secs),
+            ("influxdb.flush_interval_ms", influx.flush_interval_ms),
+            ("influxdb.batch_size", influx.batch_size.map(|v| v as u64)),
+            (
+                "influxdb.max_in_flight",
+                influx.max_in_flight.map(|v| v as u64),
+            ),
+        ] {
+            if value == Some(0) {
+                issue(key, "must be above zero".into());
+            }
+        }
+        for (key, names) in [
+            ("influxdb.tags", &influx.tags),
+            ("influxdb.fields", &influx.fields),
+        ] {
+            for name in names.iter().flatten() {
+                if InfluxAttr::parse(name).is_none() {
+                    let known: Vec<&str> = InfluxAttr::ALL.iter().map(|a| a.name()).collect();
+                    issue(
+                        key,
+                        format!("unknown attribute '{}', use {}", name, known.join(", ")),
+                    );
+                }
+            }
+        }
+        if let Some(tags) = &influx.tags
+            && influx.fields.is_none()
+        {
+            for name in tags {
+                if InfluxSchema::default()
+                    .fields
+                    .iter()
+                    .any(|f| f.name() == name.trim())
+                {
+                    issue(
+                        "influxdb.tags",
+                        format!("'{}' is a field by default, set fields without it", name),
+                    );
+                }
+            }
+        }
+        if let Some(fields) = &influx.fields {
+            for name in fields {
+                if tag_names.contains(&name.trim()) {
+                    issue("influxdb.fields", format!("'{}' is a tag already", name));
+                }
+            }
+            if fields.iter().all(|f| {
+                InfluxAttr::parse(f)
+                    .is_none_or(|a| a == InfluxAttr::Suspect || tag_names.contains(&a.name()))
+            }) {
+                issue(
+                    "influxdb.fields",
+                    "needs an attribute besides suspect, a line must have a field".into(),
+                );
+            }
+        }
+    }
+    let static_tags = cfg
+        .influxdb
+        .iter()
+        .flat_map(|i| i.static_tags.iter().flatten())
+        .map(|(k, v)| (format!("influxdb.static_tags.{}", k), k, v));
+    let ecu_tags = cfg.ecus.iter().flatten().enumerate().flat_map(|(i, ecu)| {
+        ecu.static_tags
+            .iter()
+            .flatten()
+            .map(move |(k, v)| (format!("ecus.{}.static_tags.{}", i, k), k, v))
+    });
+    for (key, tag, value) in static_tags.chain(ecu_tags) {
+        if value.is_empty() {
+            issue(&key, "must not be empty".into());
+        }
+        if tag_names.contains(&tag.as_str()) {
+            issue(&key, format!("'{}' is written from the entry already", tag));
+        }
+    }
+
+    if let Some(capture) = &cfg.capture
+        && capture.max_bytes == Some(0)
+    {
+        issue("capture.max_bytes", "must be above zero".into());
+    }
+
+    if let Some(output) = &cfg.output
+        && let Some(format) = &output.format
+        && let Err(e) = OutputFormat::parse(format, output.template.as_deref())
+    {
+        issue("output.format", e);
+    }
+
+    if let Some(logfile) = &cfg.logfile
+        && let Some(format) = &logfile.format
+        && let Err(e) = OutputFormat::parse(format, logfile.template.as_deref())
+    {
+        issue("logfile.format", e);
+    }
+
+    if let Some(time) = &cfg.time
+        && let Some(mode) = &time.mode
+        && let Err(e) = TimeMode::parse(
+            mode,
+            time.timezone.as_deref(),
+            time.auto_round_secs.unwrap_or(900),
+        )
+    {
+        issue("time.mode", e);
+    }
+
+    if let Some(validation) = &cfg.validation {
+        if let Some(p) = &validation.policy
+            && ValidationPolicy::parse(p).is_none()
+        {
+            issue("validation.policy", format!("unknown policy '{}'", p));
+        }
+        if validation.max_future_secs.is_some_and(|s| s < 0) {
+            issue("validation.max_future_secs", "must not be negative".into());
+        }
+    }
+
+    if let Some(reload) = &cfg.reload
+        && reload.watch_interval_secs == Some(0)
+    {
+        issue("reload.watch_interval_secs", "must be above zero".into());
+    }
+
+    if let Some(shutdown) = &cfg.shutdown
+        && shutdown.timeout_secs == Some(0)
+    {
+        issue("shutdown.timeout_secs", "must be above zero".into());
+    }
+
+    if let Some(api) = &cfg.api {
+        if let Some(listen) = &api.listen
+            && listen.parse::<SocketAddr>().is_err()
+        {
+            issue(
+                "api.listen",
+                format!("'{}' is not an address like 127.0.0.1:8080", listen),
+            );
+        }
+        if api.max_events == Some(0) {
+            issue("api.max_events", "must be above zero".into());
+        }
+    }
+
+    issues
+}
+
+/// Line and column (1-based) of a dotted key in the TOML source.
+pub fn key_position(raw: &str, key: &str) -> Option<(usize, usize)> {
+    let root = DeTable::parse(raw).ok()?;
+    let mut table = root.get_ref();
+    let mut segments = key.split('.').peekable();
+    let mut value: Option<&DeValue> = None;
+
+    while let Some(segment) = segments.next() {
+        if let Some(DeValue::Array(array)) = value {
+            let item = array.get(segment.parse::<usize>().ok()?)?;
+            if segments.peek().is_none() {
+                return Some(line_column(raw, item.span().start));
+            }
+            let DeValue::Table(t) = item.get_ref() else {
+                return None;
+            };
+            table = t;
+            value = None;
+            continue;
+        }
+        let (k, v) = table.iter().find(|(k, _)| k.get_ref() == segment)?;
+        if segments.peek().is_none() {
+            return Some(line_column(raw, k.span().start));
+        }
+        match v.get_ref() {
+            DeValue::Table(t) => table = t,
+            other => value = Some(other),
+        }
+    }
+    None
+}
+
+fn line_column(raw: &str, offset: usize) -> (usize, usize) {
+    let before = &raw[..offset.min(raw.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    (line, column)
+}
@@ -0,0 +1,179 @@
+use std::cmp::Ordering;
+use std::fs;
+
+use serde::de::DeserializeOwned;
+use toml::{Table, Value};
+
+/// Prefix of all configuration environment variables.
+pub const ENV_PREFIX: &str = "VILOG_";
+/// Suffix that makes the variable name a file to read the value from
+/// (e.g. `VILOG_INFLUXDB__TOKEN_FILE=/run/secrets/influx_token`).
+pub const FILE_SUFFIX: &str = "_FILE";
+
+/// Applies `VILOG_<SECTION>__<KEY>` variables on top of the parsed config file.
+///
+/// Nested tables are separated with `__` as well; entries of array tables are
+/// addressed by their index (`VILOG_ECUS__0__ADDRESS`, the next free index adds an
+/// entry). A value is set with the type the key has in `T`: `1234` is a number for
+/// `mqtt.port` and a string for `mqtt.password`. Wrap it in double quotes to force
+/// a string (`VILOG_MQTT__PASSWORD='"1234"'`). `*_FILE` variants read the value
+/// from the given file (a trailing newline is removed) and always set a string,
+/// which fits Docker secrets and systemd credentials.
+///
+/// Returns the keys that were overridden (for logging, without values).
+pub fn apply_env_overrides<T, I>(table: &mut Table, vars: I) -> Result<Vec<String>, String>
+where
+    T: DeserializeOwned,
+    I: IntoIterator<Item = (String, String)>,
+{
+    let mut overrides = Vec::new();
+    for (name, raw) in vars {
+        let Some(rest) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        // VILOG_CONFIG, VILOG_STRICT, ... are no config keys
+        if !rest.contains("__") {
+            continue;
+        }
+
+        let (rest, candidates) = match rest.strip_suffix(FILE_SUFFIX) {
+            Some(key) => {
+                let content = fs::read_to_string(&raw)
+                    .map_err(|e| format!("{}: can not read '{}': {}", name, raw, e))?;
+                let content = content.strip_suffix('\n').unwrap_or(&content);
+                let content = content.strip_suffix('\r').unwrap_or(content);
+                (key, vec![Value::String(content.to_string())])
+            }
+            None => (rest, env_value_candidates(&raw)),
+        };
+
+        let path: Vec<String> = rest.split("__").map(|p| p.to_ascii_lowercase()).collect();
+        if path.iter().any(|p| p.is_empty()) {
+            return Err(format!("{}: invalid key path", name));
+        }
+        overrides.push((name, path, candidates));
+    }
+    // the environment is unordered; ECUS__0 has to exist before ECUS__1 is added
+    overrides.sort_by(|(_, a, _), (_, b, _)| compare_paths(a, b));
+
+    let mut applied = Vec::new();
+    // values that fit no type of T; set last, so they do not hide the type of later keys
+    let mut misfits = Vec::new();
+
+    for (name, path, candidates) in overrides {
+        let mut fitted = false;
+        for value in &candidates {
+            let mut trial = table.clone();
+            set_path(&mut trial, &path, value.clone()).map_err(|e| format!("{}: {}", name, e))?;
+            if fits::<T>(&trial) {
+                *table = trial;
+                fitted = true;
+                break;
+            }
+        }
+        if !fitted {
+            misfits.push((name, path.clone(), candidates[0].clone()));
+        }
+        applied.push(path.join("."));
+    }
+
+    // the type error is reported when the configuration is read
+    for (name, path, value) in misfits {
+        set_path(table, &path, value).map_err(|e| format!("{}: {}", name, e))?;
+    }
+
+    Ok(applied)
+}
+
+/// Orders key paths segment by segment, array indexes by their number (`2` before `10`).
+fn compare_paths(a: &[String], b: &[String]) -> Ordering {
+    for (x, y) in a.iter().zip(b) {
+        let ordering = match (x.parse::<usize>(), y.parse::<usize>()) {
+            (Ok(x), Ok(y)) => x.cmp(&y),
+            _ => x.cmp(y),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+fn fits<T: DeserializeOwned>(table: &Table) -> bool {
+    Value::Table(table.clone()).try_into::<T>().is_ok()
+}
+
+/// The values `raw` may stand for, the most specific first.
+fn env_value_candidates(raw: &str) -> Vec<Value> {
+    let trimmed = raw.trim();
+    if trimmed.len() >= 2 && trimmed.starts_with('"') && trimmed.ends_with('"') {
+        return vec![Value::String(trimmed[1..trimmed.len() - 1].to_string())];
+    }
+    let mut candidates = Vec::new();
+    match trimmed {
+        "true" => candidates.push(Value::Boolean(true)),
+        "false" => candidates.push(Value::Boolean(false)),
+        _ => {}
+    }
+    if let Ok(i) = trimmed.parse::<i64>() {
+        candidates.push(Value::Integer(i));
+    }
+    if let Ok(f) = trimmed.parse::<f64>()
+        && !trimmed.chars().any(|c| c.is_ascii_alphabetic())
+    {
+        candidates.push(Value::Float(f));
+    }
+    candidates.push(Value::String(raw.to_string()));
+    candidates
+}
+
+fn set_path(table: &mut Table, path: &[String], value: Value) -> Result<(), String> {
+    let (key, rest) = path.split_first().expect("path is never empty");
+    if rest.is_empty() {
+        table.insert(key.clone(), value);
+        return Ok(());
+    }
+    let entry = table.entry(key.clone()).or_insert_with(|| {
+        if rest[0].parse::<usize>().is_ok() {
+            Value::Array(Vec::new())
+        } else {
+            Value::Table(Table::new())
+        }
+    });
+    set_in(entry, key, rest, value)
+}
+
+/// Sets `path` below `current`, which is the value of the key `name`.
+fn set_in(current: &mut Value, name: &str, path: &[String], value: Value) -> Result<(), String> {
+    match current {
+        Value::Table(t) => set_path(t, path, value),
+        Value::Array(entries) => {
+            let (index, rest) = path.split_first().expect("path is never empty");
+            let index: usize = index.parse().map_err(|_| {
+                format!(
+                    "'{}' is an array, use an index instead of '{}'",
+                    name, index
+                )
+            })?;
+            if index > entries.len() {
+                return Err(format!(
+                    "'{}' has {} entries, the next one is {}",
+                    name,
+                    entries.len(),
+                    entries.len()
+                ));
+            }
+            if index == entries.len() {
+                entries.push(Value::Table(Table::new()));
+            }
+            let name = format!("{}.{}", name, index);
+            if rest.is_empty() {
+                entries[index] = value;
+                Ok(())
+            } else {
+                set_in(&mut entries[index], &name, rest, value)
+            }
+        }
+        _ => Err(format!("'{}' is not a table", name)),
+    }
+}
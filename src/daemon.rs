@@ -0,0 +1,704 @@
+use std::error::Error;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use rumqttc::QoS;
+use tokio::sync::{mpsc, watch};
+use tokio::task::{self, JoinHandle};
+use tokio::time;
+
+use crate::api::{self, ApiState, SharedState};
+use crate::capture::CaptureWriter;
+use crate::config::{
+    AppConfig, CaptureResolved, InfluxResolved, LogFileResolved, StateResolved, StoreResolved,
+    TopicsResolved, resolve_api, resolve_capture, resolve_influx, resolve_logfile, resolve_mqtt,
+    resolve_output, resolve_reload, resolve_shutdown, resolve_state, resolve_store,
+    resolve_systemd, resolve_time, resolve_topics, resolve_validation,
+};
+use crate::dtc::ActiveDtcList;
+use crate::event::EventRecord;
+use crate::logfile::LogFileWriter;
+use crate::mqtt::{self, MqttClient, MqttEvent, MqttEventLoop};
+use crate::output::Output;
+use crate::processor::DtcProcessor;
+use crate::schedule::{PollResult, Schedule};
+use crate::state::DedupState;
+use crate::store::EventStore;
+use crate::systemd::{Journal, Notifier};
+use crate::{config, reload};
+
+/// Pause before reconnecting after a connection error.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// The topics and ECUs of `app_cfg`; the active errors are read if the API is enabled.
+pub fn topics_of(app_cfg: &AppConfig) -> TopicsResolved {
+    resolve_topics(
+        app_cfg.topics.as_ref(),
+        app_cfg.ecus.as_deref(),
+        resolve_api(app_cfg.api.as_ref()).enabled,
+    )
+}
+
+/// The processor of the history lists, with the validation and time settings of `app_cfg`.
+pub fn create_processor(app_cfg: &AppConfig, topics: &TopicsResolved) -> DtcProcessor {
+    DtcProcessor::new(
+        topics.clone(),
+        &resolve_validation(app_cfg.validation.as_ref()),
+        &resolve_time(app_cfg.time.as_ref()),
+    )
+}
+
+/// The outputs of `app_cfg`: console, InfluxDB, event store and log file.
+/// journald is connected by the [`Daemon`] only.
+pub fn create_output(app_cfg: &AppConfig, topics: &TopicsResolved) -> Output {
+    let influx_resolved = resolve_influx(app_cfg.influxdb.as_ref());
+    let http_client = create_http_client(&influx_resolved);
+    let mut output = Output::new(
+        topics,
+        resolve_output(app_cfg.output.as_ref()),
+        influx_resolved,
+        http_client,
+    );
+    output.set_store(open_store(&resolve_store(app_cfg.store.as_ref())));
+    output.set_logfile(create_logfile(&resolve_logfile(app_cfg.logfile.as_ref())));
+    output
+}
+
+/// Runs the logger until SIGINT/SIGTERM or a fatal MQTT error.
+///
+/// Reloads the configuration from `VILOG_CONFIG` on the reload triggers. Returns
+/// `false` if ViLog should exit with an error: after a fatal MQTT error, or if
+/// InfluxDB writes were lost or the state could not be saved at shutdown.
+pub async fn run(app_cfg: AppConfig, strict: bool) -> Result<bool, Box<dyn Error>> {
+    let mqtt_cfg = resolve_mqtt(app_cfg.mqtt.as_ref())?;
+    let reload_cfg = resolve_reload(app_cfg.reload.as_ref());
+    let mut daemon = Daemon::new(app_cfg);
+
+    log::info!(
+        "Connecting to '{}' (MQTT {:?})",
+        mqtt_cfg.host,
+        mqtt_cfg.protocol
+    );
+    daemon.start_api(&mqtt_cfg.host).await;
+
+    let (client, mut eventloop) = mqtt::create_client(&mqtt_cfg, 10);
+    let publisher = daemon.spawn_requests(client.clone(), mqtt_cfg.command_qos);
+
+    let config_path = config::config_path();
+    let mut reload_rx = reload::spawn_triggers(&config_path, &reload_cfg);
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
+    // set after a connection error; the event loop reconnects on the next poll
+    let mut reconnect_at: Option<time::Instant> = None;
+
+    // the signal that ended the loop; `None` after a fatal MQTT error
+    let signal = loop {
+        let event = tokio::select! {
+            event = eventloop.poll(), if reconnect_at.is_none() => event,
+            _ = time::sleep_until(reconnect_at.unwrap_or_else(time::Instant::now)),
+                if reconnect_at.is_some() =>
+            {
+                reconnect_at = None;
+                continue;
+            }
+            signal = &mut shutdown => break Some(signal),
+            Some(()) = reload_rx.recv() => {
+                match config::load_app_config(&config_path, strict) {
+                    Ok(Some(new_cfg)) => daemon.reload(new_cfg).await,
+                    Ok(None) => {
+                        log::error!("Reload failed, no configuration found; keeping the current one")
+                    }
+                    Err(e) => {
+                        log::error!("Reload failed, keeping the current configuration: {}", e)
+                    }
+                }
+                continue;
+            }
+        };
+
+        match event {
+            Ok(MqttEvent::Publish(msg)) => {
+                daemon
+                    .handle_message(&msg.topic, &msg.payload, msg.retain, Utc::now())
+                    .await;
+            }
+            Ok(MqttEvent::Connected { session_present }) => {
+                if let Some(topics) = daemon.connected(session_present) {
+                    // not awaited here: the request queue only drains while the loop polls
+                    task::spawn(subscribe(client.clone(), topics, mqtt_cfg.subscribe_qos));
+                }
+            }
+            Ok(MqttEvent::Subscribed) => daemon.subscribed(),
+            Ok(MqttEvent::Disconnected | MqttEvent::Other) => {}
+            Err(e) if e.fatal => {
+                log::error!("MQTT connection to '{}' failed: {}", mqtt_cfg.host, e);
+                break None;
+            }
+            Err(e) => {
+                log::warn!(
+                    "MQTT connection to '{}' failed: {}; reconnecting in {}s",
+                    mqtt_cfg.host,
+                    e,
+                    RECONNECT_DELAY.as_secs()
+                );
+                daemon.disconnected();
+                reconnect_at = Some(time::Instant::now() + RECONNECT_DELAY);
+            }
+        }
+    };
+
+    match signal {
+        Some(signal) => log::info!("{} received, shutting down", signal),
+        None => log::info!("Shutting down after the MQTT error"),
+    }
+    // no more commands; the devices' answers would not be processed anyway
+    publisher.abort();
+
+    let deadline = time::Instant::now() + daemon.shutdown_timeout();
+    let finished = daemon.shutdown(deadline).await;
+
+    if signal.is_some() {
+        match time::timeout_at(deadline, disconnect(&client, &mut eventloop)).await {
+            Ok(()) => log::info!("Disconnected from '{}'", mqtt_cfg.host),
+            Err(_) => log::warn!("Disconnecting from '{}' timed out", mqtt_cfg.host),
+        }
+    }
+
+    Ok(signal.is_some() && finished)
+}
+
+/// Everything that reacts to the MQTT connection: the processor, the outputs, the
+/// API state and the command schedule, with the running configuration.
+///
+/// [`run`] drives it from the MQTT event loop; it works without a broker as well.
+pub struct Daemon {
+    running: AppConfig,
+    topics: TopicsResolved,
+    processor: DtcProcessor,
+    output: Output,
+    capture: Option<CaptureWriter>,
+    api: Option<SharedState>,
+    notifier: Notifier,
+    /// The commands to publish, picked up by the requests task
+    commands: watch::Sender<TopicsResolved>,
+    /// The received lists, which shorten the interval after a possible gap
+    polls: mpsc::UnboundedSender<PollResult>,
+    polls_rx: Option<mpsc::UnboundedReceiver<PollResult>>,
+    /// Subscriptions not granted yet; READY=1 is sent when the last one is
+    pending_subscriptions: usize,
+    /// The first connection subscribes even to a stored session, the topics may have changed
+    first_connection: bool,
+}
+
+impl Daemon {
+    /// Daemon for `app_cfg`; restores the saved state if `[state]` is enabled.
+    pub fn new(app_cfg: AppConfig) -> Self {
+        let topics = topics_of(&app_cfg);
+        let mut processor = create_processor(&app_cfg, &topics);
+        let mut output = create_output(&app_cfg, &topics);
+
+        let state_cfg = resolve_state(app_cfg.state.as_ref());
+        if state_cfg.enabled {
+            restore_state(&state_cfg, &mut processor);
+        }
+
+        let capture = create_capture(&resolve_capture(app_cfg.capture.as_ref()));
+
+        let systemd_cfg = resolve_systemd(app_cfg.systemd.as_ref());
+        output.set_journal(connect_journal(systemd_cfg.journal));
+        let notifier = if systemd_cfg.notify {
+            Notifier::from_env()
+        } else {
+            Notifier::new(None, None)
+        };
+        if let Some(interval) = notifier.watchdog_interval() {
+            log::info!(
+                "systemd watchdog: pinging at most every {}s while history lists arrive",
+                interval.as_secs()
+            );
+        }
+
+        let (commands, _) = watch::channel(topics.clone());
+        let (polls, polls_rx) = mpsc::unbounded_channel();
+        Self {
+            running: app_cfg,
+            topics,
+            processor,
+            output,
+            capture,
+            api: None,
+            notifier,
+            commands,
+            polls,
+            polls_rx: Some(polls_rx),
+            pending_subscriptions: 0,
+            first_connection: true,
+        }
+    }
+
+    /// The running configuration.
+    pub fn config(&self) -> &AppConfig {
+        &self.running
+    }
+
+    /// The running topics and ECUs.
+    pub fn topics(&self) -> &TopicsResolved {
+        &self.topics
+    }
+
+    /// The processor with the last history lists.
+    pub fn processor(&self) -> &DtcProcessor {
+        &self.processor
+    }
+
+    /// The state shown by the HTTP API, if it runs.
+    pub fn api_state(&self) -> Option<&SharedState> {
+        self.api.as_ref()
+    }
+
+    /// Starts the HTTP API if it is enabled.
+    pub async fn start_api(&mut self, mqtt_host: &str) {
+        let cfg = resolve_api(self.running.api.as_ref());
+        if !cfg.enabled {
+            return;
+        }
+        let mut state = ApiState::new(&self.topics, mqtt_host, cfg.max_events);
+        state.set_influx(self.output.influx_status());
+        let state = state.shared();
+        match api::serve(&cfg, state.clone()).await {
+            Ok(()) => self.api = Some(state),
+            Err(e) => log::error!("Failed to start the HTTP API on '{}': {}", cfg.listen, e),
+        }
+    }
+
+    /// Spawns the task that publishes the commands on their schedules; it follows
+    /// reloaded commands. Can be called once.
+    pub fn spawn_requests(&mut self, client: MqttClient, command_qos: QoS) -> JoinHandle<()> {
+        let polls = self
+            .polls_rx
+            .take()
+            .expect("the requests task is spawned once");
+        let commands = self.commands.subscribe();
+        task::spawn(async move {
+            requests(client, commands, polls, command_qos).await;
+            time::sleep(Duration::from_secs(3)).await;
+        })
+    }
+
+    /// Processes a message received at `received_at`: records it to the capture,
+    /// emits the new entries of a history list and updates the API state.
+    pub async fn handle_message(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        retain: bool,
+        received_at: DateTime<Utc>,
+    ) {
+        let payload_str = std::str::from_utf8(payload).unwrap_or_default();
+
+        if let Some(capture) = self.capture.as_mut() {
+            capture.record(topic, &String::from_utf8_lossy(payload), retain);
+        }
+
+        if let Some(ecu) = self.topics.ecu_for_active_errors(topic) {
+            match serde_json::from_str::<ActiveDtcList>(payload_str) {
+                Ok(list) => {
+                    if let Some(state) = &self.api {
+                        state.lock().unwrap().record_active_faults(
+                            &ecu.ecuid,
+                            &list.list_entries,
+                            received_at,
+                        );
+                    }
+                }
+                Err(e) => log::error!("Failed to parse payload on '{}': {}", topic, e),
+            }
+            return;
+        }
+
+        let diff = match self.processor.process(topic, payload_str, received_at) {
+            Ok(diff) => diff,
+            Err(e) => {
+                log::error!("Failed to parse payload on '{}': {}", topic, e);
+                return;
+            }
+        };
+        let Some(ecu) = self.topics.ecu_for_topic(topic) else {
+            return;
+        };
+        self.output.emit(&ecu.ecuid, &diff).await;
+        let Some(did) = ecu.did_for_topic(topic) else {
+            return;
+        };
+        self.notifier.watchdog(Instant::now());
+        if let Some(state) = &self.api {
+            let records = |entries: &[_]| -> Vec<EventRecord> {
+                entries
+                    .iter()
+                    .map(|e| EventRecord::new(e, &self.topics.systemid, &ecu.ecuid))
+                    .collect()
+            };
+            let list = self
+                .processor
+                .last_list(topic)
+                .map(|l| records(&l.list_entries))
+                .unwrap_or_default();
+            state
+                .lock()
+                .unwrap()
+                .record_poll(&ecu.ecuid, did, records(&diff), list, received_at);
+        }
+        let _ = self.polls.send(PollResult {
+            ecuid: ecu.ecuid.clone(),
+            did,
+            new_entries: diff.len(),
+            gap: self.processor.gap().is_some(),
+        });
+    }
+
+    /// The broker accepted the connection. Returns the topics to subscribe to: on the
+    /// first connection and whenever the broker kept no session.
+    pub fn connected(&mut self, session_present: bool) -> Option<Vec<String>> {
+        if let Some(state) = &self.api {
+            state.lock().unwrap().mqtt_connected();
+        }
+        if !self.first_connection && session_present {
+            return None;
+        }
+        if !self.first_connection {
+            log::info!("The broker kept no session, subscribing again");
+        }
+        self.first_connection = false;
+        let topics: Vec<String> = self
+            .topics
+            .subscribed_topics()
+            .into_iter()
+            .map(String::from)
+            .collect();
+        self.pending_subscriptions = topics.len();
+        Some(topics)
+    }
+
+    /// The broker granted a subscription; READY=1 is sent with the last one.
+    pub fn subscribed(&mut self) {
+        if self.pending_subscriptions == 0 {
+            return;
+        }
+        self.pending_subscriptions -= 1;
+        if self.pending_subscriptions == 0 {
+            let mut status = format!(
+                "Subscribed to {} history topics",
+                self.topics.history_topics().len()
+            );
+            if self.topics.active_errors {
+                status += &format!(" and {} active error lists", self.topics.ecus.len());
+            }
+            log::info!("{}", status);
+            self.notifier.ready(&status);
+        }
+    }
+
+    /// The connection to the broker broke.
+    pub fn disconnected(&mut self) {
+        if let Some(state) = &self.api {
+            state.lock().unwrap().mqtt_disconnected();
+        }
+    }
+
+    /// Applies a reloaded configuration, as far as that is possible without a restart.
+    pub async fn reload(&mut self, new_cfg: AppConfig) {
+        let (new_cfg, new_topics, changes) = reload::merge(&self.running, &self.topics, new_cfg);
+        if changes.topics || changes.output || changes.influxdb {
+            let influx_resolved = resolve_influx(new_cfg.influxdb.as_ref());
+            let http_client = create_http_client(&influx_resolved);
+            self.output
+                .reconfigure(
+                    &new_topics,
+                    resolve_output(new_cfg.output.as_ref()),
+                    influx_resolved,
+                    http_client,
+                )
+                .await;
+        }
+        if changes.topics {
+            self.processor.set_topics(new_topics.clone());
+            self.commands.send_replace(new_topics.clone());
+        }
+        if changes.capture {
+            self.capture = create_capture(&resolve_capture(new_cfg.capture.as_ref()));
+        }
+        if changes.store {
+            self.output
+                .set_store(open_store(&resolve_store(new_cfg.store.as_ref())));
+        }
+        if changes.systemd {
+            self.output.set_journal(connect_journal(
+                resolve_systemd(new_cfg.systemd.as_ref()).journal,
+            ));
+        }
+        if changes.logfile {
+            self.output
+                .set_logfile(create_logfile(&resolve_logfile(new_cfg.logfile.as_ref())));
+        }
+        if changes.time {
+            self.processor
+                .set_time(&resolve_time(new_cfg.time.as_ref()));
+        }
+        if changes.validation {
+            self.processor
+                .set_validation(&resolve_validation(new_cfg.validation.as_ref()));
+        }
+
+        let live = changes.live();
+        if !live.is_empty() {
+            log::info!("Configuration reloaded, applied: {}", live.join(", "));
+        } else if changes.restart.is_empty() {
+            log::info!("Configuration reloaded, nothing changed");
+        }
+        if !changes.restart.is_empty() {
+            log::warn!(
+                "Configuration changes that need a restart (not applied): {}",
+                changes.restart.join(", ")
+            );
+        }
+
+        self.running = new_cfg;
+        self.topics = new_topics;
+        if let Some(state) = &self.api {
+            let mut state = state.lock().unwrap();
+            state.set_topics(&self.topics);
+            state.set_influx(self.output.influx_status());
+        }
+    }
+
+    /// How long the shutdown may take (`[shutdown] timeout_secs`).
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(resolve_shutdown(self.running.shutdown.as_ref()).timeout_secs)
+    }
+
+    /// Waits (until `deadline`) for the pending InfluxDB writes and saves the state.
+    /// Returns `false` if writes were lost or the state could not be saved.
+    pub async fn shutdown(&mut self, deadline: time::Instant) -> bool {
+        self.notifier.stopping();
+        let flushed = match time::timeout_at(deadline, self.output.flush()).await {
+            Ok(true) => true,
+            Ok(false) => {
+                log::error!("InfluxDB writes failed, their events are lost");
+                false
+            }
+            Err(_) => {
+                log::error!(
+                    "Pending InfluxDB writes did not finish within {}s",
+                    self.shutdown_timeout().as_secs()
+                );
+                false
+            }
+        };
+
+        let state_cfg = resolve_state(self.running.state.as_ref());
+        let saved = !state_cfg.enabled || save_state(&state_cfg, &self.processor);
+        flushed && saved
+    }
+}
+
+fn create_http_client(influx: &InfluxResolved) -> Option<reqwest::Client> {
+    if !influx.enabled {
+        return None;
+    }
+    let timeout = Duration::from_secs(influx.timeout_secs);
+    match reqwest::Client::builder().timeout(timeout).build() {
+        Ok(c) => Some(c),
+        Err(e) => {
+            log::error!("Failed to create HTTP client for InfluxDB: {}", e);
+            None
+        }
+    }
+}
+
+fn create_capture(cfg: &CaptureResolved) -> Option<CaptureWriter> {
+    if !cfg.enabled {
+        return None;
+    }
+    log::info!("Recording MQTT traffic to '{}'", cfg.path);
+    Some(CaptureWriter::new(cfg))
+}
+
+fn create_logfile(cfg: &LogFileResolved) -> Option<LogFileWriter> {
+    if !cfg.enabled {
+        return None;
+    }
+    log::info!("Writing events to '{}'", cfg.path);
+    Some(LogFileWriter::new(cfg))
+}
+
+fn connect_journal(enabled: bool) -> Option<Journal> {
+    if !enabled {
+        return None;
+    }
+    match Journal::connect() {
+        Ok(journal) => {
+            log::info!("Logging events to journald");
+            Some(journal)
+        }
+        Err(e) => {
+            log::error!("Failed to connect to journald: {}", e);
+            None
+        }
+    }
+}
+
+fn restore_state(cfg: &StateResolved, processor: &mut DtcProcessor) {
+    match DedupState::load(Path::new(&cfg.path)) {
+        Ok(Some(state)) => {
+            let saved_at = state.saved_at;
+            let restored = processor.restore(state);
+            log::info!(
+                "Restored the last lists of {} topics from '{}' (saved {})",
+                restored,
+                cfg.path,
+                saved_at
+            );
+        }
+        Ok(None) => log::info!("No saved state in '{}' yet", cfg.path),
+        Err(e) => log::error!("Failed to read the saved state '{}': {}", cfg.path, e),
+    }
+}
+
+/// Saves the dedup state; returns `false` if that failed.
+fn save_state(cfg: &StateResolved, processor: &DtcProcessor) -> bool {
+    match processor.dedup_state().save(Path::new(&cfg.path)) {
+        Ok(()) => {
+            log::info!("Saved the state to '{}'", cfg.path);
+            true
+        }
+        Err(e) => {
+            log::error!("Failed to save the state to '{}': {}", cfg.path, e);
+            false
+        }
+    }
+}
+
+fn open_store(cfg: &StoreResolved) -> Option<EventStore> {
+    if !cfg.enabled {
+        return None;
+    }
+    match EventStore::open(cfg) {
+        Ok(store) => {
+            log::info!("Storing events in '{}'", cfg.path);
+            Some(store)
+        }
+        Err(e) => {
+            log::error!("Failed to open the event store '{}': {}", cfg.path, e);
+            None
+        }
+    }
+}
+
+/// Subscribes to the history and active error topics; called on every connection without a stored session.
+async fn subscribe(client: MqttClient, topics: Vec<String>, qos: QoS) {
+    for topic in topics {
+        if let Err(e) = client.subscribe(&topic, qos).await {
+            log::error!("Subscribing to '{}' failed: {}", topic, e);
+        }
+    }
+}
+
+/// Publishes the commands on their schedules.
+/// Changes of the commands are picked up from `commands` at runtime, the received
+/// lists from `polls` shorten the interval after a possible gap.
+async fn requests(
+    client: MqttClient,
+    mut commands: watch::Receiver<TopicsResolved>,
+    mut polls: mpsc::UnboundedReceiver<PollResult>,
+    command_qos: QoS,
+) {
+    let mut topics = commands.borrow_and_update().clone();
+
+    let mut schedule = Schedule::new(&topics, Instant::now());
+    loop {
+        let next_due = schedule.next_due();
+        tokio::select! {
+            _ = sleep_until(next_due) => {}
+            changed = commands.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+                topics = commands.borrow_and_update().clone();
+                schedule = Schedule::new(&topics, Instant::now());
+                continue;
+            }
+            Some(result) = polls.recv() => {
+                schedule.record(&result, Instant::now());
+                continue;
+            }
+        }
+
+        for job in schedule.take_due(Instant::now()) {
+            let pub_topic = topics.command_topic.as_str();
+            match client
+                .publish(pub_topic, command_qos, false, &job.payload)
+                .await
+            {
+                Ok(_) => log::debug!(
+                    "Published command for {} (DIDs {:?}) to '{}'",
+                    job.ecuid,
+                    job.dids,
+                    pub_topic
+                ),
+                Err(e) => log::error!("Publish failed: {e}"),
+            }
+        }
+    }
+}
+
+/// Resolves with the name of the first shutdown signal: SIGINT (Ctrl+C) or, on unix, SIGTERM.
+async fn shutdown_signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                return tokio::select! {
+                    _ = tokio::signal::ctrl_c() => "SIGINT",
+                    _ = terminate.recv() => "SIGTERM",
+                };
+            }
+            Err(e) => log::warn!("can not listen for SIGTERM: {}", e),
+        }
+    }
+
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        log::warn!("can not listen for SIGINT: {}", e);
+        std::future::pending::<()>().await;
+    }
+    "SIGINT"
+}
+
+/// Sends a DISCONNECT and polls the event loop until it went out (or the connection is gone).
+async fn disconnect(client: &MqttClient, eventloop: &mut MqttEventLoop) {
+    if let Err(e) = client.disconnect().await {
+        log::warn!("Disconnect failed: {}", e);
+        return;
+    }
+    loop {
+        match eventloop.poll().await {
+            Ok(MqttEvent::Disconnected) => return,
+            Ok(_) => {}
+            Err(e) => {
+                log::debug!("Event loop stopped while disconnecting: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+/// Sleeps until `due`, or forever if nothing is scheduled.
+async fn sleep_until(due: Option<Instant>) {
+    match due {
+        Some(due) => time::sleep_until(due.into()).await,
+        None => std::future::pending().await,
+    }
+}
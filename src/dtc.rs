@@ -1,15 +1,23 @@
+use std::collections::HashMap;
+use std::fmt;
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+/// One history list as published by open3e (e.g. on `open3e/680_258_StatusDtcHistory`).
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct ResponseDtc {
+    /// Number of entries in the list
     pub count: u32,
+    /// Number of entries the device logged in total (not sent by every device)
     pub grand_total: Option<u32>,
+    /// The entries, newest first
     #[serde(default)]
     pub list_entries: Vec<ListEntryDtc>,
 }
 impl ResponseDtc {
+    /// An empty list, the state before the first poll.
     pub fn new_empty() -> Self {
         Self {
             count: 0,
@@ -27,15 +35,40 @@ impl ResponseDtc {
     F Error     Störungsmeldungen       err
 */
 
-#[derive(Debug, Clone, Serialize)]
-#[serde(rename_all = "PascalCase")]
+/// One entry of a history list.
+#[derive(Debug, Clone)]
 pub struct ListEntryDtc {
-    #[serde(skip_serializing)]
     /// Holds the original field name used in the source (one of: "Info", "State", "Service", "Warning", "Error").
     pub state_type: String,
+    /// Numeric ID and open3e name
     pub state: State,
+    /// Device date/time
     pub date_time: EntryDateTime,
+    /// The `Unknown` value sent by open3e, part of the identity of the entry
     pub unknown: i64,
+    /// Timestamp (ms) after clock/timezone correction; `None` if the device timestamp is used as is.
+    pub corrected_timestamp: Option<i64>,
+    /// Set by the validation (policy "mark") if the date/time of the entry looks wrong.
+    pub suspect: bool,
+    /// Number of identical entries (same [`EventKey`]) older than this one in the list.
+    pub occurrence: u32,
+}
+
+// Serialized in the open3e format again, with the state under its original field name,
+// so a saved list reads back with the same entry types
+impl Serialize for ListEntryDtc {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(Some(3))?;
+        map.serialize_entry(&self.state_type, &self.state)?;
+        map.serialize_entry("DateTime", &self.date_time)?;
+        map.serialize_entry("Unknown", &self.unknown)?;
+        map.end()
+    }
 }
 
 // Custom deserialization to capture which alias field name (Info/State/Service/Warning/Error)
@@ -116,87 +149,292 @@ impl<'de> serde::Deserialize<'de> for ListEntryDtc {
             state,
             date_time: h.date_time,
             unknown: h.unknown,
+            corrected_timestamp: None,
+            suspect: false,
+            occurrence: 0,
         })
     }
 }
 
+/// Identity of an entry: two entries with the same key are the same event.
+///
+/// The device timestamp is used, not the corrected one, so the key does not change
+/// with the `[time]` settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EventKey<'a> {
+    /// Entry type as sent by open3e (`Info`, `Service`, `State`, `Warning`, `Error`)
+    pub kind: &'a str,
+    /// Numeric ID of the message
+    pub id: i64,
+    /// Device timestamp (ms)
+    pub timestamp: i64,
+    /// The `Unknown` value of the entry
+    pub unknown: i64,
+}
+
 impl PartialEq for ListEntryDtc {
     fn eq(&self, other: &Self) -> bool {
-        self.state == other.state
-            && self.date_time == other.date_time
-            && self.unknown == other.unknown
+        self.key() == other.key()
     }
 }
 impl Eq for ListEntryDtc {}
 
 impl ListEntryDtc {
+    /// The identity of this entry, see [`EventKey`].
+    pub fn key(&self) -> EventKey<'_> {
+        EventKey {
+            kind: &self.state_type,
+            id: self.state.id,
+            timestamp: self.date_time.timestamp,
+            unknown: self.unknown,
+        }
+    }
+
+    /// Stable ID of this event of ECU `ecuid` in system `systemid` (16 hex digits).
+    ///
+    /// Derived from the [`EventKey`] and the [`ListEntryDtc::occurrence`], so the same
+    /// event gets the same ID in every run and every output.
+    pub fn event_id(&self, systemid: &str, ecuid: &str) -> String {
+        let key = self.key();
+        let identity = format!(
+            "{}\x1f{}\x1f{}\x1f{}\x1f{}\x1f{}\x1f{}",
+            systemid, ecuid, key.kind, key.id, key.timestamp, key.unknown, self.occurrence
+        );
+        format!("{:016x}", fnv1a_64(identity.as_bytes()))
+    }
+
+    /// The timestamp (ms) to use for all outputs: the corrected one if set, else the device timestamp.
+    pub fn timestamp_ms(&self) -> i64 {
+        self.corrected_timestamp.unwrap_or(self.date_time.timestamp)
+    }
+
+    /// ISO 8601 representation of [`ListEntryDtc::timestamp_ms`].
+    /// Timestamps chrono can not represent are returned as `invalid:<ms>` instead of panicking.
     pub fn get_iso8601_from_timestamp(&self) -> String {
-        let datetime = DateTime::<Utc>::from_timestamp_millis(self.date_time.timestamp)
-            .expect("Invalid timestamp");
-        format!("{}", datetime.format("%+"))
+        match DateTime::<Utc>::from_timestamp_millis(self.timestamp_ms()) {
+            Some(datetime) => format!("{}", datetime.format("%+")),
+            None => format!("invalid:{}", self.timestamp_ms()),
+        }
     }
 
+    /// Syslog severity of the entry type (`info`, `notice`, `debug`, `warning`, `err`).
     pub fn get_severity(&self) -> String {
-        match self.state_type.as_str() {
-            "Info" => "info".to_string(),
-            "Service" => "notice".to_string(),
-            "State" => "debug".to_string(),
-            "Warning" => "warning".to_string(),
-            "Error" => "err".to_string(),
-            _ => "info".to_string(),
-        }
+        severity(&self.state_type).to_string()
     }
 
+    /// Letter of the Viessmann message code (`I`, `P`, `S`, `A`, `F`).
     pub fn get_msg_code_letter(&self) -> String {
-        match self.state_type.as_str() {
-            "Info" => 'I'.to_string(),
-            "Service" => 'P'.to_string(),
-            "State" => 'S'.to_string(),
-            "Warning" => 'A'.to_string(),
-            "Error" => 'F'.to_string(),
-            _ => 'I'.to_string(),
-        }
+        msg_code_letter(&self.state_type).to_string()
+    }
+
+    /// Viessmann message code as shown on the display, e.g. `F.221`.
+    pub fn get_msg_code(&self) -> String {
+        format!("{}.{}", self.get_msg_code_letter(), self.state.id)
+    }
+}
+
+/// The list of the currently active errors (e.g. on `open3e/680_265_ErrorDtcList`).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ActiveDtcList {
+    /// The active errors
+    #[serde(default)]
+    pub list_entries: Vec<ActiveDtc>,
+}
+
+/// One entry of an [`ActiveDtcList`]: the code, without the date/time of the history.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActiveDtc {
+    /// Entry type as sent by open3e (usually `Error`)
+    pub state_type: String,
+    /// Numeric ID and open3e name
+    pub state: State,
+}
+
+// Other fields of the entry are ignored, the list format differs between devices
+impl<'de> serde::Deserialize<'de> for ActiveDtc {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let mut fields = serde_json::Map::deserialize(deserializer)?;
+        let (state_type, state) = ["Error", "Warning", "Service", "Info", "State"]
+            .into_iter()
+            .find_map(|name| fields.remove(name).map(|state| (name, state)))
+            .ok_or_else(|| {
+                serde::de::Error::custom("No one of (Info/Service/Warning/Error/State) found")
+            })?;
+        Ok(ActiveDtc {
+            state_type: state_type.to_string(),
+            state: serde_json::from_value(state).map_err(serde::de::Error::custom)?,
+        })
     }
+}
 
+impl ActiveDtc {
+    /// Viessmann message code as shown on the display, e.g. `F.221`.
     pub fn get_msg_code(&self) -> String {
-        format!(
-            "{}.{}",
-            self.get_msg_code_letter(),
-            self.state.id.to_string()
-        )
+        format!("{}.{}", msg_code_letter(&self.state_type), self.state.id)
+    }
+
+    /// Syslog severity of the entry type, see [`ListEntryDtc::get_severity`].
+    pub fn get_severity(&self) -> String {
+        severity(&self.state_type).to_string()
     }
 }
 
+/// Numeric ID and open3e name of an entry.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "PascalCase")]
 pub struct State {
+    /// Numeric ID, e.g. 221 in `F.221`
     #[serde(rename = "ID")]
     pub id: i64,
+    /// open3e name of the message, e.g. `DomesticHotWaterSensor`
     pub text: String,
 }
 
+/// Device date/time of an entry, as text and as Unix timestamp in milliseconds.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "PascalCase")]
 pub struct EntryDateTime {
-    pub date_time: String, // z. B. "2025-11-09 18:50:06"
-    pub timestamp: i64,    // z. B. 1762710606000 unix timestamp in milliseconds)
+    /// Device-local date/time, e.g. `2025-11-09 18:50:06`
+    pub date_time: String,
+    /// Unix timestamp in milliseconds, e.g. `1762710606000`
+    pub timestamp: i64,
+}
+
+fn severity(state_type: &str) -> &'static str {
+    match state_type {
+        "Info" => "info",
+        "Service" => "notice",
+        "State" => "debug",
+        "Warning" => "warning",
+        "Error" => "err",
+        _ => "info",
+    }
+}
+
+fn msg_code_letter(state_type: &str) -> char {
+    match state_type {
+        "Info" => 'I',
+        "Service" => 'P',
+        "State" => 'S',
+        "Warning" => 'A',
+        "Error" => 'F',
+        _ => 'I',
+    }
 }
 
 /// Returns all `list_entries` that are contained in `new_message` but not in `old_message`.
+///
+/// Identical entries are counted: if the new list holds an entry three times and the
+/// old one twice, the newest of the three is returned. Every returned entry has its
+/// [`ListEntryDtc::occurrence`] set.
 pub fn list_entries_new_not_in_old(
     old_message: &ResponseDtc,
     new_message: &ResponseDtc,
 ) -> Vec<ListEntryDtc> {
-    new_message
-        .list_entries
-        .iter()
-        .cloned()
-        .filter(|entry| !old_message.list_entries.contains(entry))
-        .collect()
+    let mut old_counts: HashMap<EventKey, u32> =
+        HashMap::with_capacity(old_message.list_entries.len());
+    for entry in &old_message.list_entries {
+        *old_counts.entry(entry.key()).or_default() += 1;
+    }
+
+    let mut entries = new_message.list_entries.clone();
+    number_occurrences(&mut entries);
+    entries.retain(|e| e.occurrence >= old_counts.get(&e.key()).copied().unwrap_or(0));
+    entries
+}
+
+/// Sets [`ListEntryDtc::occurrence`] of every entry of a list.
+pub fn number_occurrences(entries: &mut [ListEntryDtc]) {
+    // the lists are newest first, so identical entries are numbered from the end
+    let occurrences: Vec<u32> = {
+        let mut counts: HashMap<EventKey, u32> = HashMap::with_capacity(entries.len());
+        entries
+            .iter()
+            .rev()
+            .map(|entry| {
+                let count = counts.entry(entry.key()).or_default();
+                *count += 1;
+                *count - 1
+            })
+            .collect()
+    };
+    for (entry, occurrence) in entries.iter_mut().rev().zip(occurrences) {
+        entry.occurrence = occurrence;
+    }
+}
+
+/// Signs that the device history overflowed between two polls of the same list,
+/// so entries may have been dropped before they were seen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Gap {
+    /// Every entry of the new list is unseen, none of the last list is left
+    AllNew {
+        /// Number of entries in the new list
+        entries: usize,
+    },
+    /// `GrandTotal` grew by more than the number of new entries
+    GrandTotal {
+        /// Growth of `GrandTotal` since the last list
+        increase: u32,
+        /// Number of entries that were not in the last list
+        new_entries: usize,
+    },
+}
+
+impl fmt::Display for Gap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Gap::AllNew { entries } => {
+                write!(f, "all {} entries are new, older ones may be lost", entries)
+            }
+            Gap::GrandTotal {
+                increase,
+                new_entries,
+            } => write!(
+                f,
+                "GrandTotal grew by {} but only {} entries are new",
+                increase, new_entries
+            ),
+        }
+    }
 }
 
-/// Sorts the given vector of `ListEntryDtc` by their `timestamp` (ascending).
+/// Checks a poll for a possible gap. `old_message` is the last list received on the
+/// same topic, `new_entries` the number of entries in `new_message` that were not in it.
+pub fn detect_gap(
+    old_message: &ResponseDtc,
+    new_message: &ResponseDtc,
+    new_entries: usize,
+) -> Option<Gap> {
+    if let (Some(old_total), Some(new_total)) = (old_message.grand_total, new_message.grand_total) {
+        let increase = new_total.saturating_sub(old_total);
+        if increase as usize > new_entries {
+            return Some(Gap::GrandTotal {
+                increase,
+                new_entries,
+            });
+        }
+    }
+    let entries = new_message.list_entries.len();
+    if !old_message.list_entries.is_empty() && entries > 0 && new_entries == entries {
+        return Some(Gap::AllNew { entries });
+    }
+    None
+}
+
+/// Sorts the given vector of `ListEntryDtc` by their (corrected) timestamp (ascending).
 /// This function sorts the vector in-place.
-pub fn sort_entries_by_timestamp(entries: &mut Vec<ListEntryDtc>) {
-    entries.sort_by_key(|e| e.date_time.timestamp);
+pub fn sort_entries_by_timestamp(entries: &mut [ListEntryDtc]) {
+    entries.sort_by_key(|e| e.timestamp_ms());
+}
+
+/// 64-bit FNV-1a, stable across platforms and Rust versions (unlike `DefaultHasher`).
+fn fnv1a_64(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
 }
@@ -0,0 +1,95 @@
+use serde::Serialize;
+
+use crate::dtc::ListEntryDtc;
+
+/// Flat view of one emitted DTC entry together with the system/ECU it belongs to.
+/// This is what the machine-readable output formats are built from.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EventRecord {
+    /// ISO 8601 time derived from `timestamp`
+    pub time: String,
+    /// Unix timestamp in milliseconds (after clock correction)
+    pub timestamp: i64,
+    /// Device-local date/time string as sent by open3e
+    pub date_time: String,
+    /// System id (`topics.systemid`)
+    pub systemid: String,
+    /// ECU id
+    pub ecuid: String,
+    /// Entry type as sent by open3e (`Info`, `Service`, `State`, `Warning`, `Error`)
+    #[serde(rename = "type")]
+    pub state_type: String,
+    /// Syslog severity of the type (`info`, `notice`, `debug`, `warning`, `err`)
+    pub severity: String,
+    /// Message code, e.g. `F.221`
+    pub code: String,
+    /// Numeric ID of the message
+    pub id: i64,
+    /// open3e name of the message
+    pub text: String,
+    /// The `Unknown` value of the entry
+    pub unknown: i64,
+    /// The date/time of the entry failed validation
+    pub suspect: bool,
+    /// Stable ID of the event, for deduplication downstream
+    pub event_id: String,
+}
+
+impl EventRecord {
+    /// Names of all fields, in output order (CSV header, template placeholders).
+    pub const FIELDS: [&'static str; 13] = [
+        "time",
+        "timestamp",
+        "date_time",
+        "systemid",
+        "ecuid",
+        "type",
+        "severity",
+        "code",
+        "id",
+        "text",
+        "unknown",
+        "suspect",
+        "event_id",
+    ];
+
+    /// Record of entry `e` of ECU `ecuid` in system `systemid`.
+    pub fn new(e: &ListEntryDtc, systemid: &str, ecuid: &str) -> Self {
+        Self {
+            time: e.get_iso8601_from_timestamp(),
+            timestamp: e.timestamp_ms(),
+            date_time: e.date_time.date_time.clone(),
+            systemid: systemid.to_string(),
+            ecuid: ecuid.to_string(),
+            state_type: e.state_type.clone(),
+            severity: e.get_severity(),
+            code: e.get_msg_code(),
+            id: e.state.id,
+            text: e.state.text.clone(),
+            unknown: e.unknown,
+            suspect: e.suspect,
+            event_id: e.event_id(systemid, ecuid),
+        }
+    }
+
+    /// Returns the value of a field by its name (see [`EventRecord::FIELDS`]).
+    pub fn field(&self, name: &str) -> Option<String> {
+        let value = match name {
+            "time" => self.time.clone(),
+            "timestamp" => self.timestamp.to_string(),
+            "date_time" => self.date_time.clone(),
+            "systemid" => self.systemid.clone(),
+            "ecuid" => self.ecuid.clone(),
+            "type" => self.state_type.clone(),
+            "severity" => self.severity.clone(),
+            "code" => self.code.clone(),
+            "id" => self.id.to_string(),
+            "text" => self.text.clone(),
+            "unknown" => self.unknown.to_string(),
+            "suspect" => self.suspect.to_string(),
+            "event_id" => self.event_id.clone(),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
@@ -0,0 +1,159 @@
+use crate::event::EventRecord;
+
+/// Layout of the console output.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The classic human-readable line
+    Human,
+    /// One JSON object per line
+    Json,
+    /// `key=value` pairs (logfmt)
+    Logfmt,
+    /// Comma separated values with a header line
+    Csv,
+    /// User-defined template with `{field}` placeholders
+    Template(String),
+}
+
+impl OutputFormat {
+    /// Parses the `format` setting; `template` is only used for `format = "template"`.
+    pub fn parse(format: &str, template: Option<&str>) -> Result<Self, String> {
+        match format.trim().to_ascii_lowercase().as_str() {
+            "human" | "text" => Ok(Self::Human),
+            "json" | "jsonl" => Ok(Self::Json),
+            "logfmt" => Ok(Self::Logfmt),
+            "csv" => Ok(Self::Csv),
+            "template" => {
+                let template = template.ok_or("format \"template\" needs a template")?;
+                for name in template_placeholders(template) {
+                    if !EventRecord::FIELDS.contains(&name) {
+                        return Err(format!("unknown template field '{{{}}}'", name));
+                    }
+                }
+                Ok(Self::Template(template.to_string()))
+            }
+            other => Err(format!("unknown output format '{}'", other)),
+        }
+    }
+
+    /// Whether this is the default human-readable format.
+    pub fn is_human(&self) -> bool {
+        *self == Self::Human
+    }
+
+    /// Header line printed before the first event (only CSV has one).
+    pub fn header(&self) -> Option<String> {
+        match self {
+            Self::Csv => Some(EventRecord::FIELDS.join(",")),
+            _ => None,
+        }
+    }
+
+    /// Renders one record as a line (without trailing newline).
+    pub fn format(&self, r: &EventRecord) -> String {
+        match self {
+            Self::Human => {
+                let mut line = format!(
+                    "{} ({}) {} {}[{}]: {} {} {} {}",
+                    r.time,
+                    r.date_time,
+                    r.systemid,
+                    r.ecuid,
+                    r.id,
+                    r.state_type,
+                    r.severity,
+                    r.code,
+                    r.text
+                );
+                if r.suspect {
+                    line.push_str(" [suspect]");
+                }
+                line.push_str(" event_id=");
+                line.push_str(&r.event_id);
+                line
+            }
+            Self::Json => serde_json::to_string(r).unwrap_or_default(),
+            Self::Logfmt => EventRecord::FIELDS
+                .iter()
+                .map(|name| {
+                    format!(
+                        "{}={}",
+                        name,
+                        logfmt_value(&r.field(name).unwrap_or_default())
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(" "),
+            Self::Csv => EventRecord::FIELDS
+                .iter()
+                .map(|name| csv_value(&r.field(name).unwrap_or_default()))
+                .collect::<Vec<_>>()
+                .join(","),
+            Self::Template(template) => render_template(template, r),
+        }
+    }
+}
+
+fn template_placeholders(template: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start + 1..].find('}') else {
+            break;
+        };
+        names.push(&rest[start + 1..start + 1 + len]);
+        rest = &rest[start + 1 + len + 1..];
+    }
+    names
+}
+
+fn render_template(template: &str, r: &EventRecord) -> String {
+    let mut out = String::with_capacity(template.len() + 64);
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let Some(len) = rest[start + 1..].find('}') else {
+            out.push_str(&rest[start..]);
+            return out;
+        };
+        let name = &rest[start + 1..start + 1 + len];
+        match r.field(name) {
+            Some(value) => out.push_str(&value),
+            None => out.push_str(&rest[start..start + len + 2]),
+        }
+        rest = &rest[start + len + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn logfmt_value(value: &str) -> String {
+    let needs_quotes = value.is_empty()
+        || value
+            .chars()
+            .any(|c| c == ' ' || c == '=' || c == '"' || c.is_control());
+    if !needs_quotes {
+        return value.to_string();
+    }
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            _ => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn csv_value(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -1,30 +1,231 @@
-use crate::config::InfluxResolved;
-use std::sync::Arc;
+use crate::config::{InfluxApiVersion, InfluxResolved};
+use crate::dtc::ListEntryDtc;
+use chrono::{DateTime, Utc};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde::Serialize;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinSet;
+use tokio::time;
 
-pub async fn send_to_influx(
+enum WriterMsg {
+    Lines(Vec<String>),
+    /// Answered with whether all requests since the last flush succeeded
+    Flush(oneshot::Sender<bool>),
+}
+
+/// Outcome of the InfluxDB writes so far.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct InfluxStatus {
+    /// When the last write succeeded
+    pub last_success: Option<DateTime<Utc>>,
+    /// Error of the last failed write
+    pub last_error: Option<String>,
+    /// When the last write failed
+    pub last_error_at: Option<DateTime<Utc>>,
+}
+
+impl InfluxStatus {
+    /// Whether the last write succeeded (`None` before the first write).
+    pub fn ok(&self) -> Option<bool> {
+        match (self.last_success, self.last_error_at) {
+            (None, None) => None,
+            (Some(success), Some(error)) => Some(success > error),
+            (success, _) => Some(success.is_some()),
+        }
+    }
+}
+
+/// Buffers line-protocol lines from all topics and writes them in batches.
+///
+/// A batch is sent when `batch_size` lines are collected or `flush_interval_ms`
+/// has passed. At most `max_in_flight` requests run at the same time; further
+/// batches wait, which in turn slows down [`InfluxWriter::write`] once the
+/// channel is full instead of spawning unlimited writers.
+pub struct InfluxWriter {
+    tx: mpsc::Sender<WriterMsg>,
+    status: Arc<Mutex<InfluxStatus>>,
+}
+
+impl InfluxWriter {
+    /// Starts the writer task; it runs until the writer is dropped.
+    pub fn start(client: reqwest::Client, influx: Arc<InfluxResolved>) -> Self {
+        let (tx, rx) = mpsc::channel(64);
+        let status = Arc::new(Mutex::new(InfluxStatus::default()));
+        tokio::task::spawn(run_writer(client, influx, rx, status.clone()));
+        Self { tx, status }
+    }
+
+    /// Shared status, updated after every request.
+    pub fn status(&self) -> Arc<Mutex<InfluxStatus>> {
+        self.status.clone()
+    }
+
+    /// Queues `lines` for the next batch; waits while the queue is full.
+    pub async fn write(&self, lines: Vec<String>) {
+        if self.tx.send(WriterMsg::Lines(lines)).await.is_err() {
+            log::error!("InfluxDB writer is not running; dropping lines");
+        }
+    }
+
+    /// Sends everything that is buffered and waits until all requests are finished.
+    /// Returns `false` if a request since the last flush failed (its lines are lost).
+    pub async fn flush(&self) -> bool {
+        let (done_tx, done_rx) = oneshot::channel();
+        if self.tx.send(WriterMsg::Flush(done_tx)).await.is_err() {
+            return false;
+        }
+        done_rx.await.unwrap_or(false)
+    }
+}
+
+async fn run_writer(
     client: reqwest::Client,
     influx: Arc<InfluxResolved>,
-    body: String,
-) -> Result<(), reqwest::Error> {
-    let mut url = influx.url.clone();
-    if url.ends_with('/') {
-        url.pop();
-        while url.ends_with('/') {
-            url.pop();
+    mut rx: mpsc::Receiver<WriterMsg>,
+    status: Arc<Mutex<InfluxStatus>>,
+) {
+    let batch_size = influx.batch_size.max(1);
+    let max_in_flight = influx.max_in_flight.max(1);
+    let mut ticker = time::interval(Duration::from_millis(influx.flush_interval_ms.max(1)));
+    let mut buffer: Vec<String> = Vec::new();
+    let mut in_flight: JoinSet<bool> = JoinSet::new();
+    // all requests since the last flush succeeded
+    let mut ok = true;
+
+    loop {
+        tokio::select! {
+            msg = rx.recv() => match msg {
+                Some(WriterMsg::Lines(lines)) => {
+                    buffer.extend(lines);
+                    while buffer.len() >= batch_size {
+                        let batch: Vec<String> = buffer.drain(..batch_size).collect();
+                        ok &= spawn_batch(&client, &influx, &status, &mut in_flight, max_in_flight, batch).await;
+                    }
+                }
+                Some(WriterMsg::Flush(done)) => {
+                    let batch = std::mem::take(&mut buffer);
+                    ok &= spawn_batch(&client, &influx, &status, &mut in_flight, max_in_flight, batch).await;
+                    while let Some(result) = in_flight.join_next().await {
+                        ok &= result.unwrap_or(false);
+                    }
+                    let _ = done.send(std::mem::replace(&mut ok, true));
+                }
+                None => {
+                    let batch = std::mem::take(&mut buffer);
+                    spawn_batch(&client, &influx, &status, &mut in_flight, max_in_flight, batch).await;
+                    while in_flight.join_next().await.is_some() {}
+                    return;
+                }
+            },
+            _ = ticker.tick() => {
+                let batch = std::mem::take(&mut buffer);
+                ok &= spawn_batch(&client, &influx, &status, &mut in_flight, max_in_flight, batch).await;
+            }
+        }
+        // reap finished requests
+        while let Some(result) = in_flight.try_join_next() {
+            ok &= result.unwrap_or(false);
         }
     }
-    let full_url = format!("{}/api/v2/write", url);
+}
+
+/// Starts the request for `batch`; returns `false` if a request it had to wait for failed.
+async fn spawn_batch(
+    client: &reqwest::Client,
+    influx: &Arc<InfluxResolved>,
+    status: &Arc<Mutex<InfluxStatus>>,
+    in_flight: &mut JoinSet<bool>,
+    max_in_flight: usize,
+    batch: Vec<String>,
+) -> bool {
+    let mut ok = true;
+    if batch.is_empty() {
+        return ok;
+    }
+    while in_flight.len() >= max_in_flight {
+        if let Some(result) = in_flight.join_next().await {
+            ok &= result.unwrap_or(false);
+        }
+    }
+
+    let mut body = String::with_capacity(batch.len() * 128);
+    for line in batch {
+        body.push_str(&line);
+        body.push('\n');
+    }
+    #[cfg(debug_assertions)]
+    {
+        eprint!("Sending data to InfluxDB:\n  ----\n{}  ----\n", body);
+    }
+
+    let client = client.clone();
+    let influx = influx.clone();
+    let status = status.clone();
+    in_flight.spawn(async move {
+        let error = match send_to_influx(client, influx, body).await {
+            Ok(code) if code.is_success() => None,
+            Ok(code) => Some(format!("HTTP {}", code)),
+            Err(err) => {
+                log::error!("InfluxDB write failed: {}", err);
+                Some(err.to_string())
+            }
+        };
+        let mut status = status.lock().unwrap();
+        match error {
+            None => {
+                status.last_success = Some(Utc::now());
+                true
+            }
+            Some(error) => {
+                status.last_error = Some(error);
+                status.last_error_at = Some(Utc::now());
+                false
+            }
+        }
+    });
+    ok
+}
+
+/// Sends one request to the write endpoint; returns the HTTP status (non-success is logged).
+pub async fn send_to_influx(
+    client: reqwest::Client,
+    influx: Arc<InfluxResolved>,
+    body: String,
+) -> Result<reqwest::StatusCode, reqwest::Error> {
+    let (full_url, query) = write_url(&influx);
 
     let mut req = client
         .post(full_url)
-        .query(&[
-            ("org", influx.org.as_str()),
-            ("bucket", influx.bucket.as_str()),
-            ("precision", "ms"),
-        ])
+        .query(&query)
         .header("Content-Type", "text/plain; charset=utf-8");
-    if !influx.token.is_empty() {
-        req = req.header("Authorization", format!("Token {}", influx.token));
+    let body = if influx.gzip {
+        req = req.header("Content-Encoding", "gzip");
+        gzip(body.as_bytes())
+    } else {
+        body.into_bytes()
+    };
+    match influx.api_version {
+        InfluxApiVersion::V1 => {
+            if !influx.username.is_empty() && !influx.credentials_in_query {
+                req = req.basic_auth(&influx.username, Some(&influx.password));
+            } else if !influx.token.is_empty() {
+                req = req.header("Authorization", format!("Token {}", influx.token));
+            }
+        }
+        InfluxApiVersion::V2 => {
+            if !influx.token.is_empty() {
+                req = req.header("Authorization", format!("Token {}", influx.token));
+            }
+        }
+        InfluxApiVersion::V3 => {
+            if !influx.token.is_empty() {
+                req = req.header("Authorization", format!("Bearer {}", influx.token));
+            }
+        }
     }
     let resp = req.body(body).send().await?;
     let status = resp.status();
@@ -32,15 +233,256 @@ pub async fn send_to_influx(
 
     #[cfg(debug_assertions)]
     {
-        println!("InfluxDB write returned {}: {}", status, text);
+        eprintln!("InfluxDB write returned {}: {}", status, text);
     }
 
     if !status.is_success() {
         log::warn!("InfluxDB write returned {}: {}", status, text);
     }
-    Ok(())
+    Ok(status)
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::with_capacity(data.len() / 4), Compression::default());
+    // writing into a Vec can not fail
+    encoder.write_all(data).expect("gzip into memory");
+    encoder.finish().expect("gzip into memory")
+}
+
+/// Returns the write endpoint and its query parameters for the configured API version.
+/// Timestamps are always written with millisecond precision.
+pub fn write_url(influx: &InfluxResolved) -> (String, Vec<(&'static str, String)>) {
+    let url = influx.url.trim_end_matches('/');
+
+    match influx.api_version {
+        InfluxApiVersion::V1 => {
+            let mut query = vec![
+                ("db", influx.database.clone()),
+                ("precision", "ms".to_string()),
+            ];
+            if !influx.retention_policy.is_empty() {
+                query.push(("rp", influx.retention_policy.clone()));
+            }
+            if influx.credentials_in_query && !influx.username.is_empty() {
+                query.push(("u", influx.username.clone()));
+                query.push(("p", influx.password.clone()));
+            }
+            (format!("{}/write", url), query)
+        }
+        InfluxApiVersion::V2 => (
+            format!("{}/api/v2/write", url),
+            vec![
+                ("org", influx.org.clone()),
+                ("bucket", influx.bucket.clone()),
+                ("precision", "ms".to_string()),
+            ],
+        ),
+        InfluxApiVersion::V3 => (
+            format!("{}/api/v3/write_lp", url),
+            vec![
+                ("db", influx.database.clone()),
+                ("precision", "millisecond".to_string()),
+            ],
+        ),
+    }
+}
+
+/// Attribute of an entry that can be written as tag or as field (`[influxdb] tags`, `fields`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InfluxAttr {
+    /// System id (`topics.systemid`)
+    SystemId,
+    /// ECU id
+    EcuId,
+    /// Syslog severity of the entry type, e.g. `err`
+    Severity,
+    /// Entry type as sent by open3e (`Info`, `Service`, `State`, `Warning`, `Error`)
+    Type,
+    /// Message code, e.g. `F.221`
+    Code,
+    /// Numeric ID of the message, an integer field
+    Id,
+    /// open3e name of the message
+    Text,
+    /// The `Unknown` value of the entry, an integer field
+    Unknown,
+    /// Device-local date/time as sent by the device, e.g. `2025-11-09 18:50:06`
+    DateTime,
+    /// Stable ID of the event, see [`ListEntryDtc::event_id`]
+    EventId,
+    /// Only written for entries the validation marked as suspect
+    Suspect,
+}
+
+impl InfluxAttr {
+    /// All attributes, in the order they are listed in messages.
+    pub const ALL: [Self; 11] = [
+        Self::SystemId,
+        Self::EcuId,
+        Self::Severity,
+        Self::Type,
+        Self::Code,
+        Self::Id,
+        Self::Text,
+        Self::Unknown,
+        Self::DateTime,
+        Self::EventId,
+        Self::Suspect,
+    ];
+
+    /// Parses an attribute by its [`name`](Self::name).
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        Self::ALL.into_iter().find(|a| a.name() == s)
+    }
+
+    /// Name in the config and key in the line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::SystemId => "systemid",
+            Self::EcuId => "ecuid",
+            Self::Severity => "severity",
+            Self::Type => "type",
+            Self::Code => "code",
+            Self::Id => "id",
+            Self::Text => "text",
+            Self::Unknown => "unknown",
+            Self::DateTime => "datetime",
+            Self::EventId => "event_id",
+            Self::Suspect => "suspect",
+        }
+    }
+
+    fn value(self, systemid: &str, ecuid: &str, e: &ListEntryDtc) -> Option<AttrValue> {
+        Some(match self {
+            Self::SystemId => AttrValue::Str(systemid.to_string()),
+            Self::EcuId => AttrValue::Str(ecuid.to_string()),
+            Self::Severity => AttrValue::Str(e.get_severity()),
+            Self::Type => AttrValue::Str(e.state_type.clone()),
+            Self::Code => AttrValue::Str(e.get_msg_code()),
+            Self::Id => AttrValue::Int(e.state.id),
+            Self::Text => AttrValue::Str(e.state.text.clone()),
+            Self::Unknown => AttrValue::Int(e.unknown),
+            Self::DateTime => AttrValue::Str(e.date_time.date_time.clone()),
+            Self::EventId => AttrValue::Str(e.event_id(systemid, ecuid)),
+            Self::Suspect if e.suspect => AttrValue::Bool(true),
+            Self::Suspect => return None,
+        })
+    }
+}
+
+enum AttrValue {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+}
+
+impl AttrValue {
+    /// As tag value; tags are always strings and must not be empty.
+    fn tag(&self) -> Option<String> {
+        match self {
+            Self::Str(s) if s.is_empty() => None,
+            Self::Str(s) => Some(escape_tag(s)),
+            Self::Int(i) => Some(i.to_string()),
+            Self::Bool(b) => Some(b.to_string()),
+        }
+    }
+
+    /// As typed field value: `"text"`, `118i` or `true`.
+    fn field(&self) -> String {
+        match self {
+            Self::Str(s) => escape_field_string(s),
+            Self::Int(i) => format!("{}i", i),
+            Self::Bool(b) => b.to_string(),
+        }
+    }
+}
+
+/// Which attributes of an entry are written as tags and which as fields, plus
+/// static tags (e.g. `location`) written with every line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InfluxSchema {
+    /// Attributes written as tags, in this order
+    pub tags: Vec<InfluxAttr>,
+    /// Attributes written as fields, in this order
+    pub fields: Vec<InfluxAttr>,
+    /// Tags with a fixed value (`[influxdb] static_tags`)
+    pub static_tags: Vec<(String, String)>,
+}
+
+impl Default for InfluxSchema {
+    fn default() -> Self {
+        Self {
+            tags: vec![
+                InfluxAttr::SystemId,
+                InfluxAttr::EcuId,
+                InfluxAttr::Severity,
+                InfluxAttr::Type,
+                InfluxAttr::Code,
+            ],
+            fields: vec![InfluxAttr::Text, InfluxAttr::EventId, InfluxAttr::Suspect],
+            static_tags: Vec::new(),
+        }
+    }
+}
+
+impl InfluxSchema {
+    /// Builds one line-protocol line (without trailing newline) for a DTC entry.
+    /// `ecu_tags` are the static tags of the ECU; they win over static tags of the same name.
+    pub fn line(
+        &self,
+        measurement: &str,
+        systemid: &str,
+        ecuid: &str,
+        ecu_tags: &[(String, String)],
+        e: &ListEntryDtc,
+    ) -> String {
+        let ts_ms = e.timestamp_ms(); // already in ms
+        let mut line = escape_measurement(measurement);
+
+        let tags = self.tags.iter().filter_map(|attr| {
+            let value = attr.value(systemid, ecuid, e)?.tag()?;
+            Some((attr.name().to_string(), value))
+        });
+        let static_tags = self
+            .static_tags
+            .iter()
+            .filter(|(key, _)| !ecu_tags.iter().any(|(k, _)| k == key))
+            .chain(ecu_tags)
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(key, value)| (escape_tag(key), escape_tag(value)));
+        for (key, value) in tags.chain(static_tags) {
+            line.push(',');
+            line.push_str(&key);
+            line.push('=');
+            line.push_str(&value);
+        }
+
+        let mut separator = ' ';
+        for attr in &self.fields {
+            if let Some(value) = attr.value(systemid, ecuid, e) {
+                line.push(separator);
+                line.push_str(attr.name());
+                line.push('=');
+                line.push_str(&value.field());
+                separator = ',';
+            }
+        }
+
+        // timestamp
+        line.push(' ');
+        line.push_str(&ts_ms.to_string());
+        line
+    }
+}
+
+/// Builds one line-protocol line (without trailing newline) for a DTC entry
+/// with the default schema.
+pub fn build_line(measurement: &str, systemid: &str, ecuid: &str, e: &ListEntryDtc) -> String {
+    InfluxSchema::default().line(measurement, systemid, ecuid, &[], e)
 }
 
+/// Escapes commas and spaces of a measurement name.
 pub fn escape_measurement(input: &str) -> String {
     let mut out = String::with_capacity(input.len());
     for ch in input.chars() {
@@ -55,6 +497,7 @@ pub fn escape_measurement(input: &str) -> String {
     out
 }
 
+/// Escapes commas, equal signs and spaces of a tag key or value.
 pub fn escape_tag(input: &str) -> String {
     let mut out = String::with_capacity(input.len());
     for ch in input.chars() {
@@ -69,6 +512,7 @@ pub fn escape_tag(input: &str) -> String {
     out
 }
 
+/// Quotes a string field value and escapes quotes and backslashes in it.
 pub fn escape_field_string(input: &str) -> String {
     let mut out = String::with_capacity(input.len() + 2);
     out.push('"');
@@ -0,0 +1,77 @@
+//! ViLog as a library: parse the DTC history lists that open3e publishes for
+//! Viessmann heat pumps, find the entries that are new since the last poll and
+//! turn them into InfluxDB line protocol or the console output formats.
+//!
+//! The `ViLog` binary is a thin MQTT client around this crate.
+//!
+//! ```
+//! use vilog::dtc::{self, ResponseDtc};
+//! use vilog::influxdb::build_line;
+//!
+//! let old: ResponseDtc = serde_json::from_str(r#"{"Count": 0, "ListEntries": []}"#).unwrap();
+//! let new: ResponseDtc = serde_json::from_str(
+//!     r#"{"Count": 1, "ListEntries": [{"Error": {"ID": 221, "Text": "DomesticHotWaterSensor"},
+//!         "DateTime": {"DateTime": "2025-11-10 10:12:29", "Timestamp": 1762765949000},
+//!         "Unknown": 0}]}"#,
+//! )
+//! .unwrap();
+//!
+//! let diff = dtc::list_entries_new_not_in_old(&old, &new);
+//! assert_eq!(diff.len(), 1);
+//! assert_eq!(diff[0].get_msg_code(), "F.221");
+//! assert_eq!(
+//!     build_line("vilog", "250A", "HPMU", &diff[0]),
+//!     "vilog,systemid=250A,ecuid=HPMU,severity=err,type=Error,code=F.221 \
+//!      text=\"DomesticHotWaterSensor\",event_id=\"73091fba96305cc4\" 1762765949000"
+//! );
+//! ```
+#![warn(missing_docs)]
+
+/// HTTP API with the events, ECUs and daemon status (`[api]`).
+pub mod api;
+/// Recording of the received MQTT traffic (`[capture]`).
+pub mod capture;
+/// Correction of the device timestamps (`[time]`).
+pub mod clock;
+/// Configuration file, its sections and their resolution to concrete settings.
+pub mod config;
+/// Strict checks of the configuration (unknown keys, invalid values).
+pub mod config_check;
+/// `VILOG_*` environment overrides of the configuration.
+pub mod config_env;
+/// The logger daemon: MQTT event loop, live reload and shutdown.
+pub mod daemon;
+/// open3e DTC payloads: parsing, diffing and gap detection.
+pub mod dtc;
+/// Flat event records the output formats are built from.
+pub mod event;
+/// Console output formats.
+pub mod format;
+/// InfluxDB line protocol and the batching writer.
+pub mod influxdb;
+/// Rotating file with the emitted events (`[logfile]`).
+pub mod logfile;
+/// MQTT client for the v3.1.1 and v5 protocols.
+pub mod mqtt;
+/// Emitting new entries to the console and InfluxDB.
+pub mod output;
+/// Dedup state per history topic.
+pub mod processor;
+/// Live reload of the configuration.
+pub mod reload;
+/// Replay of captured MQTT traffic.
+pub mod replay;
+/// File rotation by size and at midnight.
+pub mod rotate;
+/// Poll schedule of the open3e commands.
+pub mod schedule;
+/// Dedup state saved at shutdown and restored at start (`[state]`).
+pub mod state;
+/// Local SQLite event store and its queries (`[store]`).
+pub mod store;
+/// systemd readiness, watchdog and journald logging (`[systemd]`).
+pub mod systemd;
+/// MQTT transports (TLS, WebSocket).
+pub mod tls;
+/// Plausibility checks of the device timestamps (`[validation]`).
+pub mod validate;
@@ -0,0 +1,39 @@
+use crate::config::LogFileResolved;
+use crate::event::EventRecord;
+use crate::format::OutputFormat;
+use crate::rotate::RotatingFile;
+
+/// Writes every emitted event to a rotating file, in one of the console formats.
+pub struct LogFileWriter {
+    file: RotatingFile,
+    format: OutputFormat,
+}
+
+impl LogFileWriter {
+    /// Writer for the log file of `cfg`; the file is opened with the first record.
+    pub fn new(cfg: &LogFileResolved) -> Self {
+        Self {
+            // a CSV file gets its header at the start of every rotated file
+            file: RotatingFile::new(&cfg.path, cfg.max_bytes, cfg.max_files)
+                .with_daily(cfg.daily)
+                .with_gzip(cfg.gzip)
+                .with_header(cfg.format.header()),
+            format: cfg.format.clone(),
+        }
+    }
+
+    /// Appends `records`; errors are logged, not returned.
+    pub fn record(&mut self, records: &[EventRecord]) {
+        let result = records
+            .iter()
+            .try_for_each(|r| self.file.write_line(&self.format.format(r)))
+            .and_then(|_| self.file.flush());
+        if let Err(e) = result {
+            log::error!(
+                "Writing log file '{}' failed: {}",
+                self.file.path().display(),
+                e
+            );
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use rumqttc::v5;
+use rumqttc::v5::mqttbytes::QoS as QoS5;
+use rumqttc::{
+    AsyncClient, ConnectReturnCode, ConnectionError, Event, EventLoop, MqttOptions, Outgoing,
+    Packet, QoS, SubscribeReasonCode, TlsError,
+};
+
+use crate::config::MqttResolved;
+
+/// MQTT protocol version used to talk to the broker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MqttProtocol {
+    /// MQTT 5
+    V5,
+    /// MQTT 3.1.1, for older brokers and bridges
+    V311,
+}
+
+impl MqttProtocol {
+    /// Parses `[mqtt] protocol` (`v5`, `5`, `v311`, `3.1.1`, `v3`).
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "v5" | "5" => Some(Self::V5),
+            "v311" | "3.1.1" | "v3" => Some(Self::V311),
+            _ => None,
+        }
+    }
+}
+
+/// Maps the numeric MQTT QoS level (0, 1, 2).
+pub fn parse_qos(level: u8) -> Option<QoS> {
+    match level {
+        0 => Some(QoS::AtMostOnce),
+        1 => Some(QoS::AtLeastOnce),
+        2 => Some(QoS::ExactlyOnce),
+        _ => None,
+    }
+}
+
+/// A received PUBLISH, independent of the protocol version.
+pub struct MqttMessage {
+    /// Topic the message was published on
+    pub topic: String,
+    /// Raw payload
+    pub payload: Vec<u8>,
+    /// Retain flag (a stored message, not a fresh one)
+    pub retain: bool,
+}
+
+/// What the event loop received, independent of the protocol version.
+pub enum MqttEvent {
+    /// A message on a subscribed topic
+    Publish(MqttMessage),
+    /// The broker accepted the connection; without a stored session the
+    /// subscriptions are gone and have to be made again
+    Connected {
+        /// The broker still had the session of an earlier connection
+        session_present: bool,
+    },
+    /// The broker granted a subscription
+    Subscribed,
+    /// The DISCONNECT requested with [`MqttClient::disconnect`] was sent
+    Disconnected,
+    /// Anything else (acks, pings, outgoing packets, refused subscriptions)
+    Other,
+}
+
+/// Client handle of either protocol version.
+#[derive(Clone)]
+pub enum MqttClient {
+    /// Client of an MQTT 5 connection
+    V5(v5::AsyncClient),
+    /// Client of an MQTT 3.1.1 connection
+    V311(AsyncClient),
+}
+
+/// Event loop of either protocol version.
+pub enum MqttEventLoop {
+    /// Event loop of an MQTT 5 connection
+    V5(Box<v5::EventLoop>),
+    /// Event loop of an MQTT 3.1.1 connection
+    V311(Box<EventLoop>),
+}
+
+type ClientResult = Result<(), Box<dyn Error + Send + Sync>>;
+
+/// A failed poll of the event loop. The next poll reconnects, unless the error is fatal.
+#[derive(Debug)]
+pub struct MqttError {
+    /// Reconnecting can not help: the broker refused the credentials or the
+    /// client ID, or the TLS settings can not be used.
+    pub fatal: bool,
+    message: String,
+}
+
+impl fmt::Display for MqttError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for MqttError {}
+
+impl From<ConnectionError> for MqttError {
+    fn from(e: ConnectionError) -> Self {
+        let fatal = match &e {
+            ConnectionError::ConnectionRefused(code) => !matches!(
+                code,
+                ConnectReturnCode::Success | ConnectReturnCode::ServiceUnavailable
+            ),
+            ConnectionError::Tls(tls) => tls_is_fatal(tls),
+            ConnectionError::InvalidUrl(_) => true,
+            _ => false,
+        };
+        Self {
+            fatal,
+            message: e.to_string(),
+        }
+    }
+}
+
+impl From<v5::ConnectionError> for MqttError {
+    fn from(e: v5::ConnectionError) -> Self {
+        use v5::mqttbytes::v5::ConnectReturnCode as Code;
+
+        let fatal = match &e {
+            v5::ConnectionError::ConnectionRefused(code) => matches!(
+                code,
+                Code::RefusedProtocolVersion
+                    | Code::BadClientId
+                    | Code::UnsupportedProtocolVersion
+                    | Code::ClientIdentifierNotValid
+                    | Code::BadUserNamePassword
+                    | Code::NotAuthorized
+                    | Code::Banned
+                    | Code::BadAuthenticationMethod
+            ),
+            v5::ConnectionError::Tls(tls) => tls_is_fatal(tls),
+            v5::ConnectionError::InvalidUrl(_) => true,
+            _ => false,
+        };
+        Self {
+            fatal,
+            message: e.to_string(),
+        }
+    }
+}
+
+/// Errors in the TLS settings, as opposed to failed handshakes.
+fn tls_is_fatal(e: &TlsError) -> bool {
+    matches!(
+        e,
+        TlsError::Addr(_)
+            | TlsError::DNSName(_)
+            | TlsError::NoValidCertInChain
+            | TlsError::NoValidClientCertInChain
+            | TlsError::NoValidKeyInChain
+    )
+}
+
+/// Creates client and event loop for the configured protocol version and transport.
+pub fn create_client(cfg: &MqttResolved, cap: usize) -> (MqttClient, MqttEventLoop) {
+    let keep_alive = Duration::from_secs(cfg.keep_alive_secs);
+    match cfg.protocol {
+        MqttProtocol::V5 => {
+            let mut options = v5::MqttOptions::new(&cfg.client_id, &cfg.host, cfg.port);
+            options.set_credentials(&cfg.username, &cfg.password);
+            options.set_keep_alive(keep_alive);
+            options.set_transport(cfg.transport.clone());
+            options.set_clean_start(!cfg.persistent_session);
+            if cfg.persistent_session {
+                options.set_session_expiry_interval(Some(cfg.session_expiry_secs));
+            }
+            let (client, eventloop) = v5::AsyncClient::new(options, cap);
+            (
+                MqttClient::V5(client),
+                MqttEventLoop::V5(Box::new(eventloop)),
+            )
+        }
+        MqttProtocol::V311 => {
+            let mut options = MqttOptions::new(&cfg.client_id, &cfg.host, cfg.port);
+            options.set_credentials(&cfg.username, &cfg.password);
+            options.set_keep_alive(keep_alive);
+            options.set_transport(cfg.transport.clone());
+            options.set_clean_session(!cfg.persistent_session);
+            let (client, eventloop) = AsyncClient::new(options, cap);
+            (
+                MqttClient::V311(client),
+                MqttEventLoop::V311(Box::new(eventloop)),
+            )
+        }
+    }
+}
+
+impl MqttClient {
+    /// Queues a SUBSCRIBE for `topic`; the grant arrives as [`MqttEvent::Subscribed`].
+    pub async fn subscribe(&self, topic: &str, qos: QoS) -> ClientResult {
+        match self {
+            Self::V5(c) => c.subscribe(topic, qos_v5(qos)).await?,
+            Self::V311(c) => c.subscribe(topic, qos).await?,
+        }
+        Ok(())
+    }
+
+    /// Queues a PUBLISH of `payload` to `topic`.
+    pub async fn publish(
+        &self,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+        payload: &str,
+    ) -> ClientResult {
+        match self {
+            Self::V5(c) => {
+                c.publish(topic, qos_v5(qos), retain, payload.to_string())
+                    .await?
+            }
+            Self::V311(c) => c.publish(topic, qos, retain, payload).await?,
+        }
+        Ok(())
+    }
+
+    /// Asks the event loop to send a DISCONNECT; it stops after that.
+    pub async fn disconnect(&self) -> ClientResult {
+        match self {
+            Self::V5(c) => c.disconnect().await?,
+            Self::V311(c) => c.disconnect().await?,
+        }
+        Ok(())
+    }
+}
+
+impl MqttEventLoop {
+    /// Waits for the next event; everything but PUBLISH packets is logged at debug level.
+    pub async fn poll(&mut self) -> Result<MqttEvent, MqttError> {
+        match self {
+            Self::V5(eventloop) => match eventloop.poll().await? {
+                v5::Event::Incoming(v5::Incoming::Publish(p)) => {
+                    Ok(MqttEvent::Publish(MqttMessage {
+                        topic: String::from_utf8_lossy(&p.topic).into_owned(),
+                        payload: p.payload.to_vec(),
+                        retain: p.retain,
+                    }))
+                }
+                other => {
+                    log::debug!("Event = {:?}", other);
+                    Ok(match other {
+                        v5::Event::Incoming(v5::Incoming::ConnAck(ack)) => MqttEvent::Connected {
+                            session_present: ack.session_present,
+                        },
+                        v5::Event::Outgoing(Outgoing::Disconnect) => MqttEvent::Disconnected,
+                        v5::Event::Incoming(v5::Incoming::SubAck(ack)) => {
+                            subscribed(ack.return_codes.iter().all(|c| {
+                                matches!(c, v5::mqttbytes::v5::SubscribeReasonCode::Success(_))
+                            }))
+                        }
+                        _ => MqttEvent::Other,
+                    })
+                }
+            },
+            Self::V311(eventloop) => match eventloop.poll().await? {
+                Event::Incoming(Packet::Publish(p)) => Ok(MqttEvent::Publish(MqttMessage {
+                    topic: p.topic,
+                    payload: p.payload.to_vec(),
+                    retain: p.retain,
+                })),
+                other => {
+                    log::debug!("Event = {:?}", other);
+                    Ok(match other {
+                        Event::Incoming(Packet::ConnAck(ack)) => MqttEvent::Connected {
+                            session_present: ack.session_present,
+                        },
+                        Event::Outgoing(Outgoing::Disconnect) => MqttEvent::Disconnected,
+                        Event::Incoming(Packet::SubAck(ack)) => subscribed(
+                            ack.return_codes
+                                .iter()
+                                .all(|c| matches!(c, SubscribeReasonCode::Success(_))),
+                        ),
+                        _ => MqttEvent::Other,
+                    })
+                }
+            },
+        }
+    }
+}
+
+fn subscribed(granted: bool) -> MqttEvent {
+    if granted {
+        MqttEvent::Subscribed
+    } else {
+        log::error!("The broker refused a subscription");
+        MqttEvent::Other
+    }
+}
+
+fn qos_v5(qos: QoS) -> QoS5 {
+    match qos {
+        QoS::AtMostOnce => QoS5::AtMostOnce,
+        QoS::AtLeastOnce => QoS5::AtLeastOnce,
+        QoS::ExactlyOnce => QoS5::ExactlyOnce,
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::config::{InfluxResolved, OutputResolved, TopicsResolved};
+use crate::dtc::ListEntryDtc;
+use crate::event::EventRecord;
+use crate::format::OutputFormat;
+use crate::influxdb::{InfluxSchema, InfluxStatus, InfluxWriter};
+use crate::logfile::LogFileWriter;
+use crate::store::EventStore;
+use crate::systemd::Journal;
+
+/// Writes new DTC entries to the console (in the configured format) and (if enabled)
+/// to InfluxDB, the event store, the log file and journald.
+pub struct Output {
+    systemid: String,
+    format: OutputFormat,
+    header_written: bool,
+    measurement: String,
+    schema: InfluxSchema,
+    /// Static InfluxDB tags per ECU id
+    ecu_tags: HashMap<String, Vec<(String, String)>>,
+    influx_writer: Option<InfluxWriter>,
+    store: Option<EventStore>,
+    logfile: Option<LogFileWriter>,
+    journal: Option<Journal>,
+}
+
+impl Output {
+    /// Outputs for the ECUs of `topics`; InfluxDB writes need `http_client`.
+    pub fn new(
+        topics: &TopicsResolved,
+        output: OutputResolved,
+        influx: InfluxResolved,
+        http_client: Option<reqwest::Client>,
+    ) -> Self {
+        let influx_writer = match (http_client, influx.enabled) {
+            (Some(client), true) => Some(InfluxWriter::start(client, Arc::new(influx.clone()))),
+            _ => None,
+        };
+        Self {
+            systemid: topics.systemid.clone(),
+            format: output.format,
+            header_written: false,
+            measurement: influx.measurement,
+            schema: influx.schema,
+            ecu_tags: topics
+                .ecus
+                .iter()
+                .map(|ecu| (ecu.ecuid.clone(), ecu.static_tags.clone()))
+                .collect(),
+            influx_writer,
+            store: None,
+            logfile: None,
+            journal: None,
+        }
+    }
+
+    /// Applies new settings at runtime. Buffered InfluxDB lines are written with the
+    /// old settings first; the CSV header is printed again only if the format changed.
+    pub async fn reconfigure(
+        &mut self,
+        topics: &TopicsResolved,
+        output: OutputResolved,
+        influx: InfluxResolved,
+        http_client: Option<reqwest::Client>,
+    ) {
+        self.flush().await;
+        let header_written = self.header_written && self.format == output.format;
+        let (store, logfile, journal) =
+            (self.store.take(), self.logfile.take(), self.journal.take());
+        *self = Self::new(topics, output, influx, http_client);
+        self.header_written = header_written;
+        self.store = store;
+        self.logfile = logfile;
+        self.journal = journal;
+    }
+
+    /// Status of the InfluxDB writes; `None` if InfluxDB is disabled.
+    pub fn influx_status(&self) -> Option<Arc<Mutex<InfluxStatus>>> {
+        self.influx_writer.as_ref().map(InfluxWriter::status)
+    }
+
+    /// Sets (or with `None` removes) the event store that every emitted entry is written to.
+    pub fn set_store(&mut self, store: Option<EventStore>) {
+        self.store = store;
+    }
+
+    /// Sets (or with `None` removes) the log file that every emitted entry is written to.
+    pub fn set_logfile(&mut self, logfile: Option<LogFileWriter>) {
+        self.logfile = logfile;
+    }
+
+    /// Sets (or with `None` removes) the journald connection every emitted entry is logged to.
+    pub fn set_journal(&mut self, journal: Option<Journal>) {
+        self.journal = journal;
+    }
+
+    /// Emits the (already sorted) entries of the ECU `ecuid`. InfluxDB lines are handed
+    /// to the batching writer; use [`Output::flush`] to wait until they are written.
+    pub async fn emit(&mut self, ecuid: &str, diff: &[ListEntryDtc]) {
+        if diff.is_empty() {
+            return;
+        }
+
+        if let Some(writer) = &self.influx_writer {
+            let ecu_tags = self.ecu_tags.get(ecuid).map_or(&[][..], Vec::as_slice);
+            let lines = diff
+                .iter()
+                .map(|e| {
+                    self.schema
+                        .line(&self.measurement, &self.systemid, ecuid, ecu_tags, e)
+                })
+                .collect();
+            writer.write(lines).await;
+        }
+
+        if !self.header_written {
+            if let Some(header) = self.format.header() {
+                println!("{}", header);
+            }
+            self.header_written = true;
+        }
+        let records: Vec<EventRecord> = diff
+            .iter()
+            .map(|e| EventRecord::new(e, &self.systemid, ecuid))
+            .collect();
+        for record in &records {
+            println!("{}", self.format.format(record));
+        }
+        if let Some(store) = &mut self.store {
+            store.record(&records);
+        }
+        if let Some(logfile) = &mut self.logfile {
+            logfile.record(&records);
+        }
+        if let Some(journal) = &self.journal {
+            journal.record(&records);
+        }
+    }
+
+    /// Waits until all buffered InfluxDB lines are written. Returns `false` if
+    /// InfluxDB lines were lost since the last flush.
+    pub async fn flush(&mut self) -> bool {
+        match &self.influx_writer {
+            Some(writer) => writer.flush().await,
+            None => true,
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+
+use crate::clock::ClockCorrector;
+use crate::config::{TimeResolved, TopicsResolved, ValidationResolved};
+use crate::dtc::{self, Gap, ListEntryDtc, ResponseDtc};
+use crate::state::DedupState;
+use crate::validate::Validator;
+
+/// Keeps the last received history list per topic and turns every new payload
+/// into the list of entries that were not seen before (sorted by timestamp).
+///
+/// Live MQTT data and replayed captures both go through this type, so the
+/// dedup behaviour is identical for both sources.
+pub struct DtcProcessor {
+    topics: TopicsResolved,
+    last_messages: HashMap<String, ResponseDtc>,
+    /// Topics whose last list comes from a saved state and was not followed by a poll yet
+    restored: HashSet<String>,
+    validator: Validator,
+    clock: ClockCorrector,
+    gap: Option<Gap>,
+}
+
+impl DtcProcessor {
+    /// Processor for `topics` with the given validation and clock correction.
+    pub fn new(
+        topics: TopicsResolved,
+        validation: &ValidationResolved,
+        time: &TimeResolved,
+    ) -> Self {
+        Self {
+            topics,
+            last_messages: HashMap::new(),
+            restored: HashSet::new(),
+            validator: Validator::new(validation.policy, validation.max_future_secs),
+            clock: ClockCorrector::new(time.mode.clone(), time.offset_secs),
+            gap: None,
+        }
+    }
+
+    /// ECU id of the ECU that publishes on `topic`.
+    pub fn ecuid(&self, topic: &str) -> Option<&str> {
+        self.topics.ecu_for_topic(topic).map(|e| e.ecuid.as_str())
+    }
+
+    /// Replaces the ECU ids and commands (the history topics stay the same).
+    pub fn set_topics(&mut self, topics: TopicsResolved) {
+        self.topics = topics;
+    }
+
+    /// Replaces the validation settings; the dedup state is kept.
+    pub fn set_validation(&mut self, validation: &ValidationResolved) {
+        self.validator = Validator::new(validation.policy, validation.max_future_secs);
+    }
+
+    /// Replaces the clock correction (an `auto` estimate starts over); the dedup state is kept.
+    pub fn set_time(&mut self, time: &TimeResolved) {
+        self.clock = ClockCorrector::new(time.mode.clone(), time.offset_secs);
+    }
+
+    /// The last history list received on `topic`.
+    pub fn last_list(&self, topic: &str) -> Option<&ResponseDtc> {
+        self.last_messages.get(topic)
+    }
+
+    /// The last list of every topic, to be saved at shutdown.
+    pub fn dedup_state(&self) -> DedupState {
+        DedupState {
+            saved_at: Utc::now(),
+            lists: self
+                .last_messages
+                .iter()
+                .map(|(topic, list)| (topic.clone(), list.clone()))
+                .collect(),
+        }
+    }
+
+    /// Restores the lists of a saved state; lists of topics that are no history topic
+    /// (any more) are dropped. Returns the number of restored topics.
+    pub fn restore(&mut self, state: DedupState) -> usize {
+        let mut restored = 0;
+        for (topic, list) in state.lists {
+            if self.topics.is_history_topic(&topic) {
+                let mut list = list;
+                dtc::number_occurrences(&mut list.list_entries);
+                self.restored.insert(topic.clone());
+                self.last_messages.insert(topic, list);
+                restored += 1;
+            }
+        }
+        restored
+    }
+
+    /// The possible gap found by the last [`DtcProcessor::process`] call, if any.
+    pub fn gap(&self) -> Option<&Gap> {
+        self.gap.as_ref()
+    }
+
+    /// Processes one payload received on `topic` at `received_at`.
+    /// Payloads on topics that are not configured are ignored (empty result).
+    pub fn process(
+        &mut self,
+        topic: &str,
+        payload: &str,
+        received_at: DateTime<Utc>,
+    ) -> Result<Vec<ListEntryDtc>, serde_json::Error> {
+        self.gap = None;
+        if !self.topics.is_history_topic(topic) {
+            return Ok(Vec::new());
+        }
+
+        let new_message: ResponseDtc = serde_json::from_str(payload)?;
+
+        let known = self.last_messages.contains_key(topic);
+        // entries new against a restored list may be from any time during the downtime
+        let fresh = known && !self.restored.remove(topic);
+        let old_message = self
+            .last_messages
+            .entry(topic.to_string())
+            .or_insert_with(ResponseDtc::new_empty);
+        let mut diff = dtc::list_entries_new_not_in_old(old_message, &new_message);
+        if known {
+            self.gap = dtc::detect_gap(old_message, &new_message, diff.len());
+            if let Some(gap) = &self.gap {
+                log::warn!("Possible gap in '{}': {}", topic, gap);
+            }
+        }
+
+        self.validator.apply(&mut diff, received_at);
+        self.clock.correct(&mut diff, received_at, fresh);
+
+        // the kept list shows the entries as they were emitted, with their corrected times
+        let mut new_message = new_message;
+        dtc::number_occurrences(&mut new_message.list_entries);
+        {
+            // new entries win over the kept ones
+            let emitted: HashMap<_, _> = old_message
+                .list_entries
+                .iter()
+                .chain(&diff)
+                .map(|e| ((e.key(), e.occurrence), (e.corrected_timestamp, e.suspect)))
+                .collect();
+            for entry in new_message.list_entries.iter_mut() {
+                if let Some(&(corrected_timestamp, suspect)) =
+                    emitted.get(&(entry.key(), entry.occurrence))
+                {
+                    entry.corrected_timestamp = corrected_timestamp;
+                    entry.suspect = suspect;
+                }
+            }
+        }
+        *old_message = new_message;
+
+        dtc::sort_entries_by_timestamp(&mut diff);
+        Ok(diff)
+    }
+}
@@ -0,0 +1,169 @@
+use std::fs;
+use std::time::{Duration, SystemTime};
+
+use tokio::sync::mpsc;
+use tokio::time;
+
+use crate::config::{AppConfig, ReloadResolved, TopicsResolved, resolve_api, resolve_topics};
+
+/// What changed between the running and the reloaded configuration.
+#[derive(Debug, Default, PartialEq)]
+pub struct Changes {
+    /// ecuid, systemid and the commands (topic, payload, interval, jitter, stagger)
+    pub topics: bool,
+    /// The `[influxdb]` section
+    pub influxdb: bool,
+    /// The `[output]` section
+    pub output: bool,
+    /// The `[capture]` section
+    pub capture: bool,
+    /// The `[store]` section
+    pub store: bool,
+    /// The `[logfile]` section
+    pub logfile: bool,
+    /// `journal` of `[systemd]`
+    pub systemd: bool,
+    /// The `[time]` section
+    pub time: bool,
+    /// The `[validation]` section
+    pub validation: bool,
+    /// Path of the saved dedup state (it is only read at start)
+    pub state: bool,
+    /// The `[shutdown]` section
+    pub shutdown: bool,
+    /// Changed settings that only take effect after a restart
+    pub restart: Vec<&'static str>,
+}
+
+impl Changes {
+    /// Names of the sections that are applied live.
+    pub fn live(&self) -> Vec<&'static str> {
+        [
+            ("topics", self.topics),
+            ("influxdb", self.influxdb),
+            ("output", self.output),
+            ("capture", self.capture),
+            ("store", self.store),
+            ("logfile", self.logfile),
+            ("systemd", self.systemd),
+            ("time", self.time),
+            ("validation", self.validation),
+            ("state", self.state),
+            ("shutdown", self.shutdown),
+        ]
+        .into_iter()
+        .filter(|(_, changed)| *changed)
+        .map(|(name, _)| name)
+        .collect()
+    }
+}
+
+/// Merges a reloaded configuration into the running one.
+///
+/// Settings that can not change without dropping the MQTT connection (the `[mqtt]`
+/// section, the subscribed topics), the `[api]` listener, `systemd.notify` and
+/// the `[reload]` section itself keep their running values and are reported in [`Changes::restart`].
+/// If the subscribed topics changed, all ECUs keep running as before. Returns the
+/// configuration and topics to run with from now on.
+pub fn merge(
+    running: &AppConfig,
+    running_topics: &TopicsResolved,
+    mut new: AppConfig,
+) -> (AppConfig, TopicsResolved, Changes) {
+    let mut changes = Changes::default();
+
+    if new.mqtt != running.mqtt {
+        changes.restart.push("mqtt");
+        new.mqtt = running.mqtt.clone();
+    }
+    if new.reload != running.reload {
+        changes.restart.push("reload");
+        new.reload = running.reload.clone();
+    }
+    if new.api != running.api {
+        changes.restart.push("api");
+        new.api = running.api.clone();
+    }
+    let notify = |cfg: &AppConfig| cfg.systemd.as_ref().and_then(|s| s.notify);
+    if notify(&new) != notify(running) {
+        changes.restart.push("systemd.notify");
+        new.systemd.get_or_insert_default().notify = notify(running);
+    }
+
+    // [api] is the running one, so the active errors stay as they are
+    let mut topics = resolve_topics(
+        new.topics.as_ref(),
+        new.ecus.as_deref(),
+        resolve_api(new.api.as_ref()).enabled,
+    );
+    if topics.subscribed_topics() != running_topics.subscribed_topics() {
+        changes.restart.push("topics/ecus (subscribed topics)");
+        topics.ecus = running_topics.ecus.clone();
+    }
+    changes.topics = topics != *running_topics;
+
+    changes.influxdb = new.influxdb != running.influxdb;
+    changes.output = new.output != running.output;
+    changes.capture = new.capture != running.capture;
+    changes.store = new.store != running.store;
+    changes.logfile = new.logfile != running.logfile;
+    changes.systemd = new.systemd != running.systemd;
+    changes.time = new.time != running.time;
+    changes.validation = new.validation != running.validation;
+    changes.state = new.state != running.state;
+    changes.shutdown = new.shutdown != running.shutdown;
+
+    (new, topics, changes)
+}
+
+/// Starts the reload triggers: SIGHUP (unix only) and, if `watch` is set, a check
+/// of the modification time of the config file every `watch_interval_secs`.
+///
+/// Every trigger sends one message; triggers that fire while a reload is pending are merged.
+pub fn spawn_triggers(path: &str, cfg: &ReloadResolved) -> mpsc::Receiver<()> {
+    let (tx, rx) = mpsc::channel(1);
+
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        match signal(SignalKind::hangup()) {
+            Ok(mut hangup) => {
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    while hangup.recv().await.is_some() {
+                        log::info!("SIGHUP received, reloading the configuration");
+                        let _ = tx.try_send(());
+                    }
+                });
+            }
+            Err(e) => log::warn!("can not listen for SIGHUP: {}", e),
+        }
+    }
+
+    if cfg.watch {
+        let path = path.to_string();
+        let interval = Duration::from_secs(cfg.watch_interval_secs.max(1));
+        tokio::spawn(async move {
+            let mut last = modified(&path);
+            let mut ticker = time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let current = modified(&path);
+                if current != last {
+                    last = current;
+                    log::info!("'{}' changed, reloading the configuration", path);
+                    if tx.send(()).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+    }
+
+    rx
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
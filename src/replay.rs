@@ -0,0 +1,154 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::config::TopicsResolved;
+use crate::output::Output;
+use crate::processor::DtcProcessor;
+
+/// One recorded publish: the topic, when it was received and the raw payload.
+#[derive(Debug, Clone)]
+pub struct ReplayRecord {
+    /// MQTT topic of the publish
+    pub topic: String,
+    /// When it was received; `None` for plain payload files
+    pub received_at: Option<DateTime<Utc>>,
+    /// Raw payload
+    pub payload: String,
+}
+
+/// Line format of a JSONL capture. `payload` may be the raw string or inline JSON.
+#[derive(Debug, Deserialize)]
+struct CaptureLine {
+    topic: String,
+    #[serde(default)]
+    received_at: Option<DateTime<Utc>>,
+    payload: serde_json::Value,
+}
+
+/// Loads replay records either from a directory of `<did>_<n>.json` files
+/// (like `tests/testdata`) or from a JSONL capture file.
+pub fn load_records(
+    path: &Path,
+    topics: &TopicsResolved,
+) -> Result<Vec<ReplayRecord>, Box<dyn Error>> {
+    if path.is_dir() {
+        load_directory(path, topics)
+    } else {
+        load_jsonl(path)
+    }
+}
+
+/// Directory files have no receive time; they are ordered by poll cycle (`<n>`)
+/// and then by DID, which matches the order open3e answers one command.
+fn load_directory(
+    dir: &Path,
+    topics: &TopicsResolved,
+) -> Result<Vec<ReplayRecord>, Box<dyn Error>> {
+    let mut files: Vec<(u32, u16, ReplayRecord)> = Vec::new();
+
+    for dir_entry in fs::read_dir(dir)? {
+        let file_path = dir_entry?.path();
+        if file_path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let Some(stem) = file_path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        let Some((did, cycle)) = parse_capture_file_name(stem) else {
+            log::warn!(
+                "Skipping '{}': file name is not <did>_<n>.json",
+                file_path.display()
+            );
+            continue;
+        };
+        let Some(topic) = topics.topic_for_did(did) else {
+            log::warn!(
+                "Skipping '{}': no topic configured for DID {}",
+                file_path.display(),
+                did
+            );
+            continue;
+        };
+        let payload = fs::read_to_string(&file_path)?;
+        files.push((
+            cycle,
+            did,
+            ReplayRecord {
+                topic: topic.to_string(),
+                received_at: None,
+                payload,
+            },
+        ));
+    }
+
+    files.sort_by_key(|(cycle, did, _)| (*cycle, *did));
+    Ok(files.into_iter().map(|(_, _, r)| r).collect())
+}
+
+fn parse_capture_file_name(stem: &str) -> Option<(u16, u32)> {
+    let (did, cycle) = stem.split_once('_')?;
+    Some((did.parse().ok()?, cycle.parse().ok()?))
+}
+
+fn load_jsonl(path: &Path) -> Result<Vec<ReplayRecord>, Box<dyn Error>> {
+    let raw = fs::read_to_string(path)?;
+    let mut records = Vec::new();
+
+    for (idx, line) in raw.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let parsed: CaptureLine = serde_json::from_str(line)
+            .map_err(|e| format!("{}:{}: {}", path.display(), idx + 1, e))?;
+        let payload = match parsed.payload {
+            serde_json::Value::String(s) => s,
+            other => other.to_string(),
+        };
+        records.push(ReplayRecord {
+            topic: parsed.topic,
+            received_at: parsed.received_at,
+            payload,
+        });
+    }
+    Ok(records)
+}
+
+/// Pushes the records through the same diff/sort/output path as live MQTT data.
+/// With `realtime` the original gaps between receive times are reproduced.
+pub async fn run(
+    records: Vec<ReplayRecord>,
+    processor: &mut DtcProcessor,
+    output: &mut Output,
+    realtime: bool,
+) {
+    let mut previous: Option<DateTime<Utc>> = None;
+
+    for record in records {
+        if realtime {
+            if let (Some(prev), Some(now)) = (previous, record.received_at)
+                && let Ok(gap) = (now - prev).to_std()
+            {
+                tokio::time::sleep(gap).await;
+            }
+            if record.received_at.is_some() {
+                previous = record.received_at;
+            }
+        }
+
+        let received_at = record.received_at.unwrap_or_else(Utc::now);
+        match processor.process(&record.topic, &record.payload, received_at) {
+            Ok(diff) => {
+                if let Some(ecuid) = processor.ecuid(&record.topic) {
+                    output.emit(ecuid, &diff).await;
+                }
+            }
+            Err(e) => log::error!("Failed to parse payload on '{}': {}", record.topic, e),
+        }
+    }
+
+    output.flush().await;
+}
@@ -0,0 +1,169 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, NaiveDate};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+/// Append-only file that is rotated by size and/or at midnight (local time):
+/// `file` -> `file.1` -> `file.2` ... (`file.1.gz` ... with gzip).
+/// At most `max_files` rotated files are kept next to the active one.
+pub struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    daily: bool,
+    gzip: bool,
+    header: Option<String>,
+    file: Option<File>,
+    size: u64,
+    /// Day the active file was started (or last written, for a file found on disk)
+    day: Option<NaiveDate>,
+}
+
+impl RotatingFile {
+    /// Rotates by size only; `max_bytes = 0` never rotates by size.
+    pub fn new(path: impl Into<PathBuf>, max_bytes: u64, max_files: usize) -> Self {
+        Self {
+            path: path.into(),
+            max_bytes,
+            max_files,
+            daily: false,
+            gzip: false,
+            header: None,
+            file: None,
+            size: 0,
+            day: None,
+        }
+    }
+
+    /// Also rotates when the first line of a new day is written.
+    pub fn with_daily(mut self, daily: bool) -> Self {
+        self.daily = daily;
+        self
+    }
+
+    /// Compresses the rotated files.
+    pub fn with_gzip(mut self, gzip: bool) -> Self {
+        self.gzip = gzip;
+        self
+    }
+
+    /// Line written at the start of every new file (e.g. a CSV header).
+    pub fn with_header(mut self, header: Option<String>) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Path of the current file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes one line (a trailing newline is added).
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        self.write_line_on(line, Local::now().date_naive())
+    }
+
+    /// Writes one line as if it was `today`.
+    pub fn write_line_on(&mut self, line: &str, today: NaiveDate) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.file.is_none() {
+            self.open(today)?;
+        }
+        let too_big = self.max_bytes > 0 && self.size + len > self.max_bytes;
+        let new_day = self.daily && self.day.is_some_and(|day| day != today);
+        if self.size > self.header_len() && (too_big || new_day) {
+            self.rotate(today)?;
+        }
+
+        let file = self.file.as_mut().expect("file opened above");
+        file.write_all(line.as_bytes())?;
+        file.write_all(b"\n")?;
+        self.size += len;
+        Ok(())
+    }
+
+    /// Flushes the current file, if one is open.
+    pub fn flush(&mut self) -> io::Result<()> {
+        match self.file.as_mut() {
+            Some(f) => f.flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn header_len(&self) -> u64 {
+        self.header.as_ref().map_or(0, |h| h.len() as u64 + 1)
+    }
+
+    fn open(&mut self, today: NaiveDate) -> io::Result<()> {
+        if let Some(parent) = self.path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let metadata = file.metadata()?;
+        self.size = metadata.len();
+        // a file left from an earlier day is rotated with the first line
+        self.day = match metadata.modified() {
+            Ok(modified) if self.size > 0 => Some(DateTime::<Local>::from(modified).date_naive()),
+            _ => Some(today),
+        };
+        if self.size == 0
+            && let Some(header) = &self.header
+        {
+            file.write_all(header.as_bytes())?;
+            file.write_all(b"\n")?;
+            self.size = self.header_len();
+        }
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn rotate(&mut self, today: NaiveDate) -> io::Result<()> {
+        self.file = None;
+
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let oldest = self.rotated_path(self.max_files);
+            if oldest.exists() {
+                fs::remove_file(&oldest)?;
+            }
+            for n in (1..self.max_files).rev() {
+                let from = self.rotated_path(n);
+                if from.exists() {
+                    fs::rename(&from, self.rotated_path(n + 1))?;
+                }
+            }
+            if self.gzip {
+                compress(&self.path, &self.rotated_path(1))?;
+                fs::remove_file(&self.path)?;
+            } else {
+                fs::rename(&self.path, self.rotated_path(1))?;
+            }
+        }
+
+        self.open(today)
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{}", n));
+        if self.gzip {
+            name.push(".gz");
+        }
+        PathBuf::from(name)
+    }
+}
+
+fn compress(from: &Path, to: &Path) -> io::Result<()> {
+    let mut encoder = GzEncoder::new(File::create(to)?, Compression::default());
+    io::copy(&mut File::open(from)?, &mut encoder)?;
+    encoder.finish()?.sync_all()
+}
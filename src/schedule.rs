@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::config::TopicsResolved;
+
+/// One scheduled open3e command.
+#[derive(Clone, Debug)]
+pub struct Job {
+    /// ECU the command is sent for
+    pub ecuid: String,
+    /// DIDs the command reads
+    pub dids: Vec<u16>,
+    /// Payload published to the command topic
+    pub payload: String,
+    interval: Duration,
+    jitter: Duration,
+    /// Planned time without jitter; the next one is `base + interval`
+    base: Instant,
+    /// When the command is published (`base` plus a random jitter)
+    due: Instant,
+    /// DIDs with a possible gap and their polls without new entries since
+    gaps: HashMap<u16, u32>,
+}
+
+impl Job {
+    /// The interval until the next run, shortened while one of the DIDs has a gap.
+    fn current_interval(&self, gap_interval: Duration) -> Duration {
+        if self.gaps.is_empty() {
+            self.interval
+        } else {
+            self.interval.min(gap_interval)
+        }
+    }
+}
+
+/// Outcome of one received history list, fed back into the [`Schedule`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct PollResult {
+    /// ECU the list came from
+    pub ecuid: String,
+    /// DID of the list
+    pub did: u16,
+    /// Number of entries that were not seen before
+    pub new_entries: usize,
+    /// The history buffer may have overflowed since the last poll
+    pub gap: bool,
+}
+
+/// Poll schedule of all commands of all ECUs.
+///
+/// Each command runs on its own interval. The first commands of consecutive ECUs are
+/// `stagger_secs` apart, and every run is delayed by a random jitter of up to
+/// `jitter_secs`, so several ECUs and commands do not hit the bus at the same moment.
+///
+/// After a possible gap in a history list the commands reading that DID run every
+/// `gap_interval_secs`, until the DID had `gap_quiet_polls` polls without new entries.
+#[derive(Clone, Debug, Default)]
+pub struct Schedule {
+    jobs: Vec<Job>,
+    gap_interval: Duration,
+    gap_quiet_polls: u32,
+}
+
+impl Schedule {
+    /// Schedule of the commands in `topics`; the first ones are due at `now`.
+    pub fn new(topics: &TopicsResolved, now: Instant) -> Self {
+        let stagger = Duration::from_secs(topics.stagger_secs);
+        let jobs = topics
+            .ecus
+            .iter()
+            .enumerate()
+            .flat_map(|(i, ecu)| {
+                let start = now + stagger * i as u32;
+                ecu.commands.iter().map(move |c| {
+                    let jitter = Duration::from_secs(c.jitter_secs);
+                    Job {
+                        ecuid: ecu.ecuid.clone(),
+                        dids: c.dids.clone(),
+                        payload: c.payload.clone(),
+                        interval: Duration::from_secs(c.interval_secs),
+                        jitter,
+                        base: start,
+                        due: start + random_jitter(jitter),
+                        gaps: HashMap::new(),
+                    }
+                })
+            })
+            .collect();
+        Self {
+            jobs,
+            gap_interval: Duration::from_secs(topics.gap_interval_secs),
+            gap_quiet_polls: topics.gap_quiet_polls,
+        }
+    }
+
+    /// When the next command is due, `None` if there are no commands.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.jobs.iter().map(|j| j.due).min()
+    }
+
+    /// Returns the commands that are due at `now` (in due order) and plans their next run.
+    pub fn take_due(&mut self, now: Instant) -> Vec<Job> {
+        let mut due: Vec<&mut Job> = self.jobs.iter_mut().filter(|j| j.due <= now).collect();
+        due.sort_by_key(|j| j.due);
+
+        let mut taken = Vec::with_capacity(due.len());
+        for job in due {
+            taken.push(job.clone());
+            let interval = job.current_interval(self.gap_interval);
+            job.base += interval;
+            if job.base <= now {
+                // do not catch up on missed runs after a stall
+                job.base = now + interval;
+            }
+            job.due = job.base + random_jitter(job.jitter);
+        }
+        taken
+    }
+
+    /// Shortens or restores the interval of the commands that read `result.did`.
+    pub fn record(&mut self, result: &PollResult, now: Instant) {
+        let jobs = self
+            .jobs
+            .iter_mut()
+            .filter(|j| j.ecuid == result.ecuid && j.dids.contains(&result.did));
+        for job in jobs {
+            if result.gap {
+                if job.gaps.insert(result.did, 0).is_some() {
+                    continue;
+                }
+                let interval = job.current_interval(self.gap_interval);
+                log::warn!(
+                    "Polling DIDs {:?} of {} every {:?} until no new entries arrive",
+                    job.dids,
+                    job.ecuid,
+                    interval
+                );
+                if job.base > now + interval {
+                    job.base = now + interval;
+                    job.due = job.base + random_jitter(job.jitter);
+                }
+            } else if let Some(quiet) = job.gaps.get_mut(&result.did) {
+                *quiet = if result.new_entries == 0 {
+                    *quiet + 1
+                } else {
+                    0
+                };
+                if *quiet >= self.gap_quiet_polls {
+                    job.gaps.remove(&result.did);
+                    if job.gaps.is_empty() {
+                        log::info!(
+                            "DIDs {:?} of {} are quiet again, polling every {:?}",
+                            job.dids,
+                            job.ecuid,
+                            job.interval
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn random_jitter(max: Duration) -> Duration {
+    if max.is_zero() {
+        return Duration::ZERO;
+    }
+    Duration::from_millis(rand::random_range(0..=max.as_millis() as u64))
+}
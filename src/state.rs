@@ -0,0 +1,45 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::dtc::ResponseDtc;
+
+/// The dedup state saved at shutdown: the last history list per topic.
+///
+/// Restored at the next start, only the entries that arrived in between are
+/// emitted instead of the whole lists again.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DedupState {
+    /// When the state was saved
+    pub saved_at: DateTime<Utc>,
+    /// The last list per history topic
+    pub lists: BTreeMap<String, ResponseDtc>,
+}
+
+impl DedupState {
+    /// Reads the state from `path`; `None` if there is no such file.
+    pub fn load(path: &Path) -> io::Result<Option<Self>> {
+        let raw = match fs::read_to_string(path) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        serde_json::from_str(&raw)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Writes the state to `path` (through a temporary file, so a crash never leaves half a file).
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, path)
+    }
+}
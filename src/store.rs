@@ -0,0 +1,323 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use rusqlite::types::Value;
+use rusqlite::{Connection, OpenFlags, params, params_from_iter};
+
+use crate::config::StoreResolved;
+use crate::event::EventRecord;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS events (
+    event_id TEXT PRIMARY KEY,
+    timestamp INTEGER NOT NULL,
+    time TEXT NOT NULL,
+    date_time TEXT NOT NULL,
+    systemid TEXT NOT NULL,
+    ecuid TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    severity TEXT NOT NULL,
+    code TEXT NOT NULL,
+    id INTEGER NOT NULL,
+    text TEXT NOT NULL,
+    unknown INTEGER NOT NULL,
+    suspect INTEGER NOT NULL,
+    stored_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS events_timestamp ON events (timestamp);
+CREATE INDEX IF NOT EXISTS events_stored_at ON events (stored_at);
+";
+
+/// How often old events are deleted while running.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Filters of `vilog query`; `None` matches everything.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EventFilter {
+    /// Events at or after this time (Unix ms)
+    pub since: Option<i64>,
+    /// Events before this time (Unix ms)
+    pub until: Option<i64>,
+    /// Message code with `*` and `?` wildcards, e.g. `F.*`
+    pub code: Option<String>,
+    /// ECU id
+    pub ecuid: Option<String>,
+    /// Severity (`err`, `warning`, ...) or type (`Error`, `Warning`, ...)
+    pub severity: Option<String>,
+}
+
+impl EventFilter {
+    /// Whether `r` passes the filter; the same rules as [`EventStore::query`].
+    pub fn matches(&self, r: &EventRecord) -> bool {
+        self.since.is_none_or(|since| r.timestamp >= since)
+            && self.until.is_none_or(|until| r.timestamp < until)
+            && self
+                .code
+                .as_ref()
+                .is_none_or(|code| glob_match(&code.to_ascii_uppercase(), &r.code))
+            && self
+                .ecuid
+                .as_ref()
+                .is_none_or(|ecuid| ecuid.eq_ignore_ascii_case(&r.ecuid))
+            && self.severity.as_ref().is_none_or(|severity| {
+                severity.eq_ignore_ascii_case(&r.severity)
+                    || severity.eq_ignore_ascii_case(&r.state_type)
+            })
+    }
+}
+
+/// `*` and `?` wildcards as in SQLite's GLOB.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), text.chars().collect());
+    let (mut p, mut t) = (0, 0);
+    // position after the last `*` and the text position it was tried at
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, t));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((after, tried)) => {
+                    p = after;
+                    t = tried + 1;
+                    star = Some((after, tried + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// SQLite database with every emitted event.
+///
+/// Events are keyed by their `event_id`, so an event that is emitted again (e.g. the
+/// full history after a restart) is stored only once.
+pub struct EventStore {
+    conn: Connection,
+    retention_days: u32,
+    last_prune: Option<Instant>,
+}
+
+impl EventStore {
+    /// Opens (or creates) the database for writing.
+    pub fn open(cfg: &StoreResolved) -> rusqlite::Result<Self> {
+        let conn = Connection::open(&cfg.path)?;
+        // readers (`vilog query`) do not block the logger
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self {
+            conn,
+            retention_days: cfg.retention_days,
+            last_prune: None,
+        })
+    }
+
+    /// Opens an existing database for queries.
+    pub fn open_read_only(path: &Path) -> rusqlite::Result<Self> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        Ok(Self {
+            conn,
+            retention_days: 0,
+            last_prune: None,
+        })
+    }
+
+    /// Stores the records and, once an hour, deletes the events older than the retention.
+    /// Errors are logged; the logger keeps running without the store.
+    pub fn record(&mut self, records: &[EventRecord]) {
+        let now = Utc::now();
+        if let Err(e) = self.insert(records, now) {
+            log::error!(
+                "Writing {} events to the store failed: {}",
+                records.len(),
+                e
+            );
+        }
+        if self
+            .last_prune
+            .is_none_or(|t| t.elapsed() >= PRUNE_INTERVAL)
+        {
+            self.last_prune = Some(Instant::now());
+            match self.prune(now) {
+                Ok(0) => {}
+                Ok(n) => log::info!("Deleted {} events older than the store retention", n),
+                Err(e) => log::error!("Deleting old events from the store failed: {}", e),
+            }
+        }
+    }
+
+    /// Inserts the records (stored at `stored_at`); returns how many were not stored before.
+    pub fn insert(
+        &mut self,
+        records: &[EventRecord],
+        stored_at: DateTime<Utc>,
+    ) -> rusqlite::Result<usize> {
+        let tx = self.conn.transaction()?;
+        let mut inserted = 0;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR IGNORE INTO events (event_id, timestamp, time, date_time, systemid,
+                    ecuid, kind, severity, code, id, text, unknown, suspect, stored_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            )?;
+            for r in records {
+                inserted += stmt.execute(params![
+                    r.event_id,
+                    r.timestamp,
+                    r.time,
+                    r.date_time,
+                    r.systemid,
+                    r.ecuid,
+                    r.state_type,
+                    r.severity,
+                    r.code,
+                    r.id,
+                    r.text,
+                    r.unknown,
+                    r.suspect,
+                    stored_at.timestamp_millis(),
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(inserted)
+    }
+
+    /// Deletes the events stored more than `retention_days` before `now`
+    /// (nothing with `retention_days = 0`); returns how many were deleted.
+    pub fn prune(&mut self, now: DateTime<Utc>) -> rusqlite::Result<usize> {
+        if self.retention_days == 0 {
+            return Ok(0);
+        }
+        let cutoff = now - chrono::Duration::days(i64::from(self.retention_days));
+        self.conn.execute(
+            "DELETE FROM events WHERE stored_at < ?1",
+            params![cutoff.timestamp_millis()],
+        )
+    }
+
+    /// Events matching `filter`, oldest first.
+    pub fn query(&self, filter: &EventFilter) -> rusqlite::Result<Vec<EventRecord>> {
+        let mut sql = "SELECT time, timestamp, date_time, systemid, ecuid, kind, severity, code,
+                id, text, unknown, suspect, event_id
+             FROM events WHERE 1 = 1"
+            .to_string();
+        let mut values: Vec<Value> = Vec::new();
+        if let Some(since) = filter.since {
+            sql.push_str(" AND timestamp >= ?");
+            values.push(since.into());
+        }
+        if let Some(until) = filter.until {
+            sql.push_str(" AND timestamp < ?");
+            values.push(until.into());
+        }
+        if let Some(code) = &filter.code {
+            sql.push_str(" AND code GLOB ?");
+            values.push(code.to_ascii_uppercase().into());
+        }
+        if let Some(ecuid) = &filter.ecuid {
+            sql.push_str(" AND ecuid = ? COLLATE NOCASE");
+            values.push(ecuid.clone().into());
+        }
+        if let Some(severity) = &filter.severity {
+            sql.push_str(" AND (severity = ? COLLATE NOCASE OR kind = ? COLLATE NOCASE)");
+            values.push(severity.clone().into());
+            values.push(severity.clone().into());
+        }
+        sql.push_str(" ORDER BY timestamp, event_id");
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(values), |row| {
+            Ok(EventRecord {
+                time: row.get(0)?,
+                timestamp: row.get(1)?,
+                date_time: row.get(2)?,
+                systemid: row.get(3)?,
+                ecuid: row.get(4)?,
+                state_type: row.get(5)?,
+                severity: row.get(6)?,
+                code: row.get(7)?,
+                id: row.get(8)?,
+                text: row.get(9)?,
+                unknown: row.get(10)?,
+                suspect: row.get(11)?,
+                event_id: row.get(12)?,
+            })
+        })?;
+        rows.collect()
+    }
+}
+
+/// Parses a `--since`/`--until` value: RFC 3339 (`2025-11-13T10:00:00Z`), a date
+/// (`2025-11-13`, midnight UTC) or a time span back from `now` (`30m`, `12h`, `7d`).
+/// Returns Unix ms.
+pub fn parse_time_arg(value: &str, now: DateTime<Utc>) -> Result<i64, String> {
+    if let Ok(t) = DateTime::parse_from_rfc3339(value) {
+        return Ok(t.timestamp_millis());
+    }
+    if let Ok(d) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(d.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis());
+    }
+    let unit = value.chars().last().unwrap_or(' ');
+    let secs = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 3600,
+        'd' => 86400,
+        _ => return Err(format!("invalid time '{}'", value)),
+    };
+    let count: i64 = value[..value.len() - 1]
+        .parse()
+        .map_err(|_| format!("invalid time '{}'", value))?;
+    count
+        .checked_mul(secs)
+        .and_then(chrono::TimeDelta::try_seconds)
+        .and_then(|span| now.checked_sub_signed(span))
+        .map(|t| t.timestamp_millis())
+        .ok_or_else(|| format!("time span '{}' is too large", value))
+}
+
+/// Renders the records as a table with aligned columns.
+pub fn format_table(records: &[EventRecord]) -> String {
+    const HEADER: [&str; 6] = ["TIME", "SYSTEM", "ECU", "CODE", "SEVERITY", "TEXT"];
+    let rows: Vec<[&str; 6]> = records
+        .iter()
+        .map(|r| {
+            [
+                r.time.as_str(),
+                r.systemid.as_str(),
+                r.ecuid.as_str(),
+                r.code.as_str(),
+                r.severity.as_str(),
+                r.text.as_str(),
+            ]
+        })
+        .collect();
+    let mut widths = HEADER.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut out = String::new();
+    for row in std::iter::once(&HEADER).chain(&rows) {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        out.push_str(cells.join("  ").trim_end());
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,228 @@
+use std::time::{Duration, Instant};
+
+use crate::event::EventRecord;
+use crate::format::OutputFormat;
+
+/// Socket of journald's native protocol.
+pub const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Notifications to the service manager (`Type=notify`, `WatchdogSec=`).
+/// Does nothing if ViLog was not started by systemd or on other platforms.
+pub struct Notifier {
+    #[cfg(unix)]
+    socket: Option<(
+        std::os::unix::net::UnixDatagram,
+        std::os::unix::net::SocketAddr,
+    )>,
+    /// Half of `WatchdogSec`
+    watchdog: Option<Duration>,
+    last_ping: Option<Instant>,
+}
+
+impl Notifier {
+    /// Uses `NOTIFY_SOCKET`, `WATCHDOG_USEC` and `WATCHDOG_PID` as set by systemd.
+    pub fn from_env() -> Self {
+        let watchdog_usec = std::env::var("WATCHDOG_USEC").ok().filter(|_| {
+            std::env::var("WATCHDOG_PID")
+                .map_or(true, |pid| pid.trim() == std::process::id().to_string())
+        });
+        Self::new(
+            std::env::var("NOTIFY_SOCKET").ok().as_deref(),
+            watchdog_usec.as_deref(),
+        )
+    }
+
+    /// Notifier for the socket `notify_socket` (a path or `@abstract` name) and a
+    /// watchdog timeout of `watchdog_usec` microseconds.
+    pub fn new(notify_socket: Option<&str>, watchdog_usec: Option<&str>) -> Self {
+        let watchdog = watchdog_usec
+            .and_then(|usec| usec.trim().parse::<u64>().ok())
+            .filter(|usec| *usec > 0)
+            .map(|usec| Duration::from_micros(usec) / 2);
+        #[cfg(not(unix))]
+        let _ = notify_socket;
+        Self {
+            #[cfg(unix)]
+            socket: notify_socket.and_then(|path| match unix::notify_socket(path) {
+                Ok(socket) => Some(socket),
+                Err(e) => {
+                    log::warn!("Can not use the systemd notify socket '{}': {}", path, e);
+                    None
+                }
+            }),
+            watchdog,
+            last_ping: None,
+        }
+    }
+
+    /// Whether `NOTIFY_SOCKET` was set, i.e. ViLog was started by systemd.
+    #[cfg(unix)]
+    pub fn is_enabled(&self) -> bool {
+        self.socket.is_some()
+    }
+
+    #[cfg(not(unix))]
+    pub fn is_enabled(&self) -> bool {
+        false
+    }
+
+    /// Interval of the watchdog pings, if systemd expects them.
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog.filter(|_| self.is_enabled())
+    }
+
+    /// Tells systemd that the service is up.
+    pub fn ready(&mut self, status: &str) {
+        self.send(&format!("READY=1\nSTATUS={}", status));
+        self.watchdog(Instant::now());
+    }
+
+    /// Sends a watchdog ping, at most once per interval. Call it whenever data arrived,
+    /// so a logger that receives nothing any more is restarted.
+    pub fn watchdog(&mut self, now: Instant) {
+        let Some(interval) = self.watchdog else {
+            return;
+        };
+        if self
+            .last_ping
+            .is_none_or(|last| now.duration_since(last) >= interval)
+        {
+            self.last_ping = Some(now);
+            self.send("WATCHDOG=1");
+        }
+    }
+
+    /// Tells systemd that the service is shutting down.
+    pub fn stopping(&self) {
+        self.send("STOPPING=1");
+    }
+
+    #[cfg(unix)]
+    fn send(&self, state: &str) {
+        if let Some((socket, addr)) = &self.socket
+            && let Err(e) = socket.send_to_addr(state.as_bytes(), addr)
+        {
+            log::warn!("systemd notification failed: {}", e);
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn send(&self, _state: &str) {}
+}
+
+/// Events logged to journald over its native protocol, so they can be filtered
+/// by their fields: `journalctl VILOG_CODE=F.454`.
+pub struct Journal {
+    #[cfg(unix)]
+    socket: std::os::unix::net::UnixDatagram,
+}
+
+impl Journal {
+    /// Connects to journald; fails if it is not running (or not on unix).
+    pub fn connect() -> std::io::Result<Self> {
+        Self::connect_to(JOURNAL_SOCKET)
+    }
+
+    /// Connects to the journald socket at `path`.
+    pub fn connect_to(path: &str) -> std::io::Result<Self> {
+        #[cfg(unix)]
+        {
+            let socket = std::os::unix::net::UnixDatagram::unbound()?;
+            socket.connect(path)?;
+            Ok(Self { socket })
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = path;
+            Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "journald is only available on Linux",
+            ))
+        }
+    }
+
+    /// Logs every record as one journal entry.
+    #[cfg(unix)]
+    pub fn record(&self, records: &[EventRecord]) {
+        for r in records {
+            if let Err(e) = self.socket.send(&encode(&journal_fields(r))) {
+                log::error!("Logging event {} to journald failed: {}", r.event_id, e);
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn record(&self, _records: &[EventRecord]) {}
+}
+
+/// Syslog priority of a severity as returned by `get_severity`.
+pub fn priority(severity: &str) -> u8 {
+    match severity {
+        "err" => 3,
+        "warning" => 4,
+        "notice" => 5,
+        "debug" => 7,
+        _ => 6,
+    }
+}
+
+/// Journal fields of one event: the human line as `MESSAGE`, the severity as
+/// `PRIORITY` and the event fields as `VILOG_*`.
+pub fn journal_fields(r: &EventRecord) -> Vec<(&'static str, String)> {
+    vec![
+        ("MESSAGE", OutputFormat::Human.format(r)),
+        ("PRIORITY", priority(&r.severity).to_string()),
+        ("SYSLOG_IDENTIFIER", "vilog".to_string()),
+        ("VILOG_CODE", r.code.clone()),
+        ("VILOG_ECUID", r.ecuid.clone()),
+        ("VILOG_SYSTEMID", r.systemid.clone()),
+        ("VILOG_TYPE", r.state_type.clone()),
+        ("VILOG_SEVERITY", r.severity.clone()),
+        ("VILOG_TEXT", r.text.clone()),
+        ("VILOG_TIME", r.time.clone()),
+        ("VILOG_EVENT_ID", r.event_id.clone()),
+    ]
+}
+
+/// Serializes fields in journald's native format: `KEY=value\n`, or for values with
+/// a newline `KEY\n`, the length as 64 bit little endian, the value and `\n`.
+pub fn encode(fields: &[(&str, String)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (key, value) in fields {
+        out.extend_from_slice(key.as_bytes());
+        if value.contains('\n') {
+            out.push(b'\n');
+            out.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            out.push(b'=');
+        }
+        out.extend_from_slice(value.as_bytes());
+        out.push(b'\n');
+    }
+    out
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::io;
+    use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+    pub fn notify_socket(path: &str) -> io::Result<(UnixDatagram, SocketAddr)> {
+        let addr = match path.strip_prefix('@') {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                SocketAddr::from_abstract_name(name)?
+            }
+            #[cfg(not(target_os = "linux"))]
+            Some(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "abstract sockets are only available on Linux",
+                ));
+            }
+            None => SocketAddr::from_pathname(path)?,
+        };
+        Ok((UnixDatagram::unbound()?, addr))
+    }
+}
@@ -0,0 +1,116 @@
+use std::error::Error;
+use std::fs;
+
+use native_tls::{Certificate, Identity, TlsConnector};
+use rumqttc::{TlsConfiguration, Transport};
+
+use crate::config::MqttConfig;
+
+/// Picks the broker transport from the `host` and the TLS settings.
+///
+/// - `ws://...`: plain WebSocket, `host` is the full URL (e.g. `ws://proxy:80/mqtt`)
+/// - `wss://...`: WebSocket over TLS, `tls` does not need to be set
+/// - otherwise TCP, with TLS if `tls = true`
+pub fn create_transport(cfg: Option<&MqttConfig>, host: &str) -> Result<Transport, Box<dyn Error>> {
+    let tls = cfg.and_then(|c| c.tls).unwrap_or(false);
+
+    if host.starts_with("wss://") {
+        return Ok(Transport::wss_with_config(create_wss_config(cfg)?));
+    }
+    if host.starts_with("ws://") {
+        if tls {
+            return Err("tls = true does not work with a ws:// host, use wss:// instead".into());
+        }
+        return Ok(Transport::Ws);
+    }
+
+    Ok(create_tls_transport(cfg)?.unwrap_or(Transport::Tcp))
+}
+
+/// Builds the TLS transport for the broker connection, or `None` if TLS is disabled.
+///
+/// - `ca_path`: PEM file with the CA certificate(s) of the broker (in addition to the system store)
+/// - `client_cert_path`/`client_key_path`: PEM client certificate and PKCS#8 key for client auth
+/// - `alpn`: ALPN protocols to offer
+/// - `accept_invalid_certs`: skip certificate and hostname checks (for testing only!)
+fn create_tls_transport(cfg: Option<&MqttConfig>) -> Result<Option<Transport>, Box<dyn Error>> {
+    let Some(cfg) = cfg.filter(|c| c.tls.unwrap_or(false)) else {
+        return Ok(None);
+    };
+
+    let mut builder = TlsConnector::builder();
+
+    if let Some(ca_path) = &cfg.ca_path {
+        let pem = read(ca_path, "CA certificate")?;
+        builder.add_root_certificate(Certificate::from_pem(&pem)?);
+    }
+
+    if let Some((cert, key)) = read_client_auth(cfg)? {
+        builder.identity(Identity::from_pkcs8(&cert, &key)?);
+    }
+
+    if let Some(alpn) = &cfg.alpn {
+        let protocols: Vec<&str> = alpn.iter().map(String::as_str).collect();
+        builder.request_alpns(&protocols);
+    }
+
+    if cfg.accept_invalid_certs.unwrap_or(false) {
+        log::warn!("MQTT TLS certificate checks are disabled (accept_invalid_certs = true)");
+        builder.danger_accept_invalid_certs(true);
+        builder.danger_accept_invalid_hostnames(true);
+    }
+
+    let connector = builder.build()?;
+    Ok(Some(Transport::tls_with_config(
+        TlsConfiguration::NativeConnector(connector),
+    )))
+}
+
+/// TLS settings for `wss://`, which rumqttc only supports with rustls.
+///
+/// With `ca_path` only that CA is trusted, otherwise the system store is used.
+/// Client certificates and ALPN need `ca_path`; `accept_invalid_certs` is not supported.
+fn create_wss_config(cfg: Option<&MqttConfig>) -> Result<TlsConfiguration, Box<dyn Error>> {
+    let Some(cfg) = cfg else {
+        return Ok(TlsConfiguration::default());
+    };
+
+    if cfg.accept_invalid_certs.unwrap_or(false) {
+        return Err("accept_invalid_certs is not supported with a wss:// host".into());
+    }
+
+    let client_auth = read_client_auth(cfg)?;
+    let Some(ca_path) = &cfg.ca_path else {
+        if client_auth.is_some() || cfg.alpn.is_some() {
+            return Err("client certificates and alpn with a wss:// host need ca_path".into());
+        }
+        return Ok(TlsConfiguration::default());
+    };
+
+    Ok(TlsConfiguration::Simple {
+        ca: read(ca_path, "CA certificate")?,
+        alpn: cfg
+            .alpn
+            .as_ref()
+            .map(|alpn| alpn.iter().map(|p| p.as_bytes().to_vec()).collect()),
+        client_auth,
+    })
+}
+
+/// PEM client certificate and key.
+type ClientAuth = (Vec<u8>, Vec<u8>);
+
+fn read_client_auth(cfg: &MqttConfig) -> Result<Option<ClientAuth>, Box<dyn Error>> {
+    match (&cfg.client_cert_path, &cfg.client_key_path) {
+        (Some(cert_path), Some(key_path)) => Ok(Some((
+            read(cert_path, "client certificate")?,
+            read(key_path, "client key")?,
+        ))),
+        (None, None) => Ok(None),
+        _ => Err("client_cert_path and client_key_path must be set together".into()),
+    }
+}
+
+fn read(path: &str, what: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    fs::read(path).map_err(|e| format!("can not read {} '{}': {}", what, path, e).into())
+}
@@ -0,0 +1,206 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+
+use crate::clock::parse_device_date_time;
+use crate::dtc::ListEntryDtc;
+
+/// Earliest plausible device timestamp (2000-01-01T00:00:00Z, in ms).
+const MIN_TIMESTAMP_MS: i64 = 946_684_800_000;
+/// Largest plausible difference between the device-local `DateTime` and the `Timestamp` (14 h).
+const MAX_LOCAL_OFFSET_MS: i64 = 14 * 3600 * 1000;
+/// Timezone offsets are whole quarter hours.
+const OFFSET_STEP_MS: i64 = 15 * 60 * 1000;
+/// Allowed jitter when comparing `DateTime` (second resolution) and `Timestamp`.
+const OFFSET_TOLERANCE_MS: i64 = 2000;
+
+/// What happens to entries that fail validation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValidationPolicy {
+    /// Drop the entry (it is logged as a warning).
+    Drop,
+    /// Rebuild the timestamp from the device-local `DateTime` (as `corrected_timestamp`,
+    /// the device timestamp and so the event ID stay unchanged); drop it if that is impossible.
+    Correct,
+    /// Pass the entry on with the `suspect` marker.
+    Mark,
+}
+
+impl ValidationPolicy {
+    /// Parses `[validation] policy` (`drop`, `correct`, `mark`).
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "drop" => Some(Self::Drop),
+            "correct" => Some(Self::Correct),
+            "mark" => Some(Self::Mark),
+            _ => None,
+        }
+    }
+}
+
+/// Why an entry failed validation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Issue {
+    /// `Timestamp` is before 2000 or can not be represented at all
+    OutOfRange,
+    /// `Timestamp` is further in the future than allowed
+    Future,
+    /// `DateTime` is not in the "YYYY-MM-DD hh:mm:ss" format
+    UnparseableDateTime,
+    /// `DateTime` and `Timestamp` differ by something that is no timezone offset
+    Inconsistent {
+        /// `DateTime` (read as UTC) minus `Timestamp`
+        offset_ms: i64,
+    },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::OutOfRange => write!(f, "timestamp out of range"),
+            Issue::Future => write!(f, "timestamp too far in the future"),
+            Issue::UnparseableDateTime => write!(f, "date/time can not be parsed"),
+            Issue::Inconsistent { offset_ms } => write!(
+                f,
+                "date/time and timestamp differ by {} s",
+                offset_ms / 1000
+            ),
+        }
+    }
+}
+
+/// Checks `EntryDateTime` of new entries and applies the [`ValidationPolicy`].
+///
+/// Runs before the [`ClockCorrector`](crate::clock::ClockCorrector), which corrects
+/// a rebuilt timestamp like a device one.
+pub struct Validator {
+    policy: ValidationPolicy,
+    max_future_ms: i64,
+    /// Offset between device-local `DateTime` and `Timestamp` of the last consistent entry.
+    local_offset_ms: Option<i64>,
+}
+
+impl Validator {
+    /// Validator with `policy`; timestamps more than `max_future_secs` ahead are invalid.
+    pub fn new(policy: ValidationPolicy, max_future_secs: i64) -> Self {
+        Self {
+            policy,
+            max_future_ms: max_future_secs.saturating_mul(1000),
+            local_offset_ms: None,
+        }
+    }
+
+    /// Returns all problems of one entry (empty if it is fine).
+    pub fn check(&self, e: &ListEntryDtc, now: DateTime<Utc>) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        let ts = e.date_time.timestamp;
+
+        if ts < MIN_TIMESTAMP_MS || DateTime::<Utc>::from_timestamp_millis(ts).is_none() {
+            issues.push(Issue::OutOfRange);
+        } else if ts > now.timestamp_millis().saturating_add(self.max_future_ms) {
+            issues.push(Issue::Future);
+        }
+
+        match parse_device_date_time(&e.date_time.date_time) {
+            None => issues.push(Issue::UnparseableDateTime),
+            Some(naive) => match naive.and_utc().timestamp_millis().checked_sub(ts) {
+                // only a timestamp far outside the range of dates overflows
+                None => {
+                    if !issues.contains(&Issue::OutOfRange) {
+                        issues.push(Issue::OutOfRange);
+                    }
+                }
+                Some(offset_ms) => {
+                    let off_step = (offset_ms.rem_euclid(OFFSET_STEP_MS))
+                        .min(OFFSET_STEP_MS - offset_ms.rem_euclid(OFFSET_STEP_MS));
+                    if offset_ms.unsigned_abs() > MAX_LOCAL_OFFSET_MS as u64
+                        || off_step > OFFSET_TOLERANCE_MS
+                    {
+                        issues.push(Issue::Inconsistent { offset_ms });
+                    }
+                }
+            },
+        }
+        issues
+    }
+
+    /// Validates `entries` in place; dropped entries are removed.
+    pub fn apply(&mut self, entries: &mut Vec<ListEntryDtc>, now: DateTime<Utc>) {
+        entries.retain_mut(|e| {
+            let issues = self.check(e, now);
+            if issues.is_empty() {
+                self.local_offset_ms = local_offset_ms(e);
+                return true;
+            }
+
+            let reasons = issues
+                .iter()
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            match self.policy {
+                ValidationPolicy::Mark => {
+                    log::warn!(
+                        "Suspect entry {} ({}): {}",
+                        e.get_msg_code(),
+                        e.date_time.date_time,
+                        reasons
+                    );
+                    e.suspect = true;
+                    true
+                }
+                ValidationPolicy::Drop => {
+                    log::warn!(
+                        "Dropping entry {} ({}): {}",
+                        e.get_msg_code(),
+                        e.date_time.date_time,
+                        reasons
+                    );
+                    false
+                }
+                ValidationPolicy::Correct => match self.corrected_timestamp(e) {
+                    Some(ts) => {
+                        log::warn!(
+                            "Correcting entry {} ({}): {}; timestamp {} -> {}",
+                            e.get_msg_code(),
+                            e.date_time.date_time,
+                            reasons,
+                            e.date_time.timestamp,
+                            ts
+                        );
+                        e.corrected_timestamp = Some(ts);
+                        true
+                    }
+                    None => {
+                        log::warn!(
+                            "Dropping entry {} ({}): {}; can not be corrected",
+                            e.get_msg_code(),
+                            e.date_time.date_time,
+                            reasons
+                        );
+                        false
+                    }
+                },
+            }
+        });
+    }
+
+    /// Rebuilds the timestamp from the device-local `DateTime` and the
+    /// offset seen on the last consistent entry (UTC if there was none yet).
+    fn corrected_timestamp(&self, e: &ListEntryDtc) -> Option<i64> {
+        let naive = parse_device_date_time(&e.date_time.date_time)?;
+        naive
+            .and_utc()
+            .timestamp_millis()
+            .checked_sub(self.local_offset_ms.unwrap_or(0))
+    }
+}
+
+/// Difference between the device-local `DateTime` (read as UTC) and the `Timestamp`.
+fn local_offset_ms(e: &ListEntryDtc) -> Option<i64> {
+    let naive = parse_device_date_time(&e.date_time.date_time)?;
+    naive
+        .and_utc()
+        .timestamp_millis()
+        .checked_sub(e.date_time.timestamp)
+}
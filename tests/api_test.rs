@@ -0,0 +1,210 @@
+use chrono::Utc;
+use serde_json::Value;
+
+use vilog::api::{self, ApiState, SharedState};
+use vilog::config;
+use vilog::dtc::{ActiveDtcList, ResponseDtc};
+use vilog::event::EventRecord;
+
+fn records(file: &str) -> Vec<EventRecord> {
+    let data = std::fs::read_to_string(format!("tests/testdata/{}.json", file)).unwrap();
+    let parsed: ResponseDtc = serde_json::from_str(&data).unwrap();
+    parsed
+        .list_entries
+        .iter()
+        .map(|e| EventRecord::new(e, "250A", "HPMU"))
+        .collect()
+}
+
+/// Serves the API on a free local port; returns its base URL.
+async fn spawn_api(state: SharedState, token: Option<&str>) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let app = api::router(state, token.map(str::to_string));
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    url
+}
+
+async fn get_json(url: &str, token: Option<&str>) -> (u16, Value) {
+    let mut request = reqwest::Client::new().get(url);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().await.unwrap();
+    let status = response.status().as_u16();
+    (
+        status,
+        serde_json::from_str(&response.text().await.unwrap()).unwrap(),
+    )
+}
+
+fn state_with_events() -> SharedState {
+    let topics = config::resolve_topics(None, None, true);
+    let mut state = ApiState::new(&topics, "broker", 1000);
+    let states = records("258_1");
+    state.record_poll("HPMU", 258, states.clone(), states, Utc::now());
+    let warnings = records("264_1");
+    state.record_poll("HPMU", 264, warnings.clone(), warnings, Utc::now());
+    state.shared()
+}
+
+#[tokio::test]
+async fn events_are_filtered_and_paginated() {
+    let url = spawn_api(state_with_events(), None).await;
+    let warnings = records("264_1").len();
+
+    let (status, page) = get_json(&format!("{}/events?severity=warning&limit=2", url), None).await;
+    assert_eq!(status, 200);
+    assert_eq!(page["total"], warnings);
+    assert_eq!(page["events"].as_array().unwrap().len(), 2);
+
+    let (_, rest) = get_json(
+        &format!("{}/events?severity=warning&limit=2&offset=2", url),
+        None,
+    )
+    .await;
+    assert_eq!(
+        rest["events"].as_array().unwrap().len(),
+        (warnings - 2).min(2)
+    );
+    assert_ne!(page["events"][0]["event_id"], rest["events"][0]["event_id"]);
+
+    let (status, error) = get_json(&format!("{}/events?since=yesterday", url), None).await;
+    assert_eq!(status, 400);
+    assert_eq!(error["error"], "invalid time 'yesterday'");
+}
+
+#[tokio::test]
+async fn ecus_show_the_active_faults_and_lists() {
+    let state = state_with_events();
+    let errors: ResponseDtc = serde_json::from_str(
+        r#"{"Count": 1, "ListEntries": [{"Error": {"ID": 221, "Text": "DomesticHotWaterSensor"},
+            "DateTime": {"DateTime": "2025-11-10 10:12:29", "Timestamp": 1762765949000},
+            "Unknown": 0}]}"#,
+    )
+    .unwrap();
+    let errors: Vec<EventRecord> = errors
+        .list_entries
+        .iter()
+        .map(|e| EventRecord::new(e, "250A", "HPMU"))
+        .collect();
+    state
+        .lock()
+        .unwrap()
+        .record_poll("HPMU", 266, errors.clone(), errors, Utc::now());
+    let url = spawn_api(state.clone(), None).await;
+
+    // an error in the history is no active fault
+    let (status, ecus) = get_json(&format!("{}/ecus", url), None).await;
+    assert_eq!(status, 200);
+    assert_eq!(ecus[0]["ecuid"], "HPMU");
+    assert_eq!(ecus[0]["discovered"], true);
+    assert_eq!(ecus[0]["active_faults"], Value::Null);
+
+    // unknown fields of the entries are ignored
+    let active: ActiveDtcList = serde_json::from_str(
+        r#"{"Count": 1, "ListEntries": [{"Error": {"ID": 221, "Text": "DomesticHotWaterSensor"},
+            "Unknown": 0}]}"#,
+    )
+    .unwrap();
+    state
+        .lock()
+        .unwrap()
+        .record_active_faults("HPMU", &active.list_entries, Utc::now());
+    let (_, ecus) = get_json(&format!("{}/ecus", url), None).await;
+    assert_eq!(ecus[0]["active_faults"].as_array().unwrap().len(), 1);
+    assert_eq!(ecus[0]["active_faults"][0]["code"], "F.221");
+    assert_eq!(ecus[0]["active_faults"][0]["severity"], "err");
+
+    let (status, lists) = get_json(&format!("{}/ecus/HPMU/history", url), None).await;
+    assert_eq!(status, 200);
+    // in the order of HISTORY_DIDS, only the received ones
+    let names: Vec<&str> = lists
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|l| l["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        names,
+        ["ErrorDtcHistory", "WarningDtcHistory", "StatusDtcHistory"]
+    );
+    assert_eq!(
+        lists[2]["entries"].as_array().unwrap().len(),
+        records("258_1").len()
+    );
+
+    let (status, _) = get_json(&format!("{}/ecus/VCMU/history", url), None).await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn bearer_token_is_required_when_configured() {
+    let url = spawn_api(state_with_events(), Some("s3cret")).await;
+
+    let (status, _) = get_json(&format!("{}/status", url), None).await;
+    assert_eq!(status, 401);
+    let (status, _) = get_json(&format!("{}/status", url), Some("wrong")).await;
+    assert_eq!(status, 401);
+
+    let (status, body) = get_json(&format!("{}/status", url), Some("s3cret")).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(body["mqtt"]["host"], "broker");
+    assert_eq!(body["mqtt"]["connected"], true);
+    assert_eq!(body["influxdb"]["enabled"], false);
+
+    // the web page asks for the token itself
+    let page = reqwest::get(format!("{}/", url)).await.unwrap();
+    assert_eq!(page.status(), 200);
+    assert!(page.text().await.unwrap().contains("<title>ViLog</title>"));
+}
+
+/// Reads Server-Sent Events until `count` events of type `dtc` arrived; returns their data.
+async fn read_sse(response: &mut reqwest::Response, count: usize) -> Vec<Value> {
+    let mut text = String::new();
+    loop {
+        let events: Vec<Value> = text
+            .split("\n\n")
+            .filter(|block| block.contains("event: dtc"))
+            .filter_map(|block| block.lines().find_map(|l| l.strip_prefix("data: ")))
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect();
+        if events.len() >= count && text.ends_with("\n\n") {
+            return events;
+        }
+        let chunk = response.chunk().await.unwrap().expect("stream ended");
+        text.push_str(std::str::from_utf8(&chunk).unwrap());
+    }
+}
+
+#[tokio::test]
+async fn stream_replays_the_last_events_and_sends_new_ones() {
+    let state = state_with_events();
+    let url = spawn_api(state.clone(), None).await;
+    let warnings = records("264_1");
+
+    let mut response = reqwest::get(format!("{}/events/stream?severity=warning&last=2", url))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    let replayed = read_sse(&mut response, 2).await;
+    // oldest first, like the live events that follow
+    assert_eq!(replayed[1]["event_id"], warnings.last().unwrap().event_id);
+
+    // a state message (filtered out) and a warning arrive
+    let (other, mut warning) = (records("258_1")[0].clone(), warnings[0].clone());
+    warning.event_id = "0000000000000001".to_string();
+    state
+        .lock()
+        .unwrap()
+        .record_poll("HPMU", 258, vec![other], Vec::new(), Utc::now());
+    state
+        .lock()
+        .unwrap()
+        .record_poll("HPMU", 264, vec![warning], Vec::new(), Utc::now());
+
+    let live = read_sse(&mut response, 1).await;
+    assert_eq!(live[0]["event_id"], "0000000000000001");
+}
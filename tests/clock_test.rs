@@ -0,0 +1,67 @@
+use chrono::{DateTime, Utc};
+
+use vilog::clock::{ClockCorrector, TimeMode};
+use vilog::dtc;
+
+/// The entry from the README sample: device-local 08:58:38 (CET), device timestamp 06:58:38Z.
+fn readme_entry() -> Vec<dtc::ListEntryDtc> {
+    let json = r#"{"Count":1,"ListEntries":[{"Warning":{"ID":100,"Text":"RestoreEepromToDefault"},
+        "DateTime":{"DateTime":"2025-11-02 08:58:38","Timestamp":1762066718000},"Unknown":1}]}"#;
+    let parsed: dtc::ResponseDtc = serde_json::from_str(json).unwrap();
+    parsed.list_entries
+}
+
+fn utc(s: &str) -> DateTime<Utc> {
+    s.parse().unwrap()
+}
+
+#[test]
+fn device_mode_keeps_timestamp() {
+    let mut entries = readme_entry();
+    ClockCorrector::new(TimeMode::Device, 0).correct(&mut entries, Utc::now(), false);
+
+    assert_eq!(entries[0].corrected_timestamp, None);
+    assert_eq!(
+        entries[0].get_iso8601_from_timestamp(),
+        "2025-11-02T06:58:38+00:00"
+    );
+}
+
+#[test]
+fn timezone_mode_uses_device_local_time() {
+    let mut entries = readme_entry();
+    let mode = TimeMode::parse("timezone", Some("Europe/Berlin"), 900).unwrap();
+    ClockCorrector::new(mode, 0).correct(&mut entries, Utc::now(), false);
+
+    assert_eq!(
+        entries[0].get_iso8601_from_timestamp(),
+        "2025-11-02T07:58:38+00:00"
+    );
+    assert!(TimeMode::parse("timezone", Some("Mars/Olympus"), 900).is_err());
+}
+
+#[test]
+fn offset_mode_adds_fixed_correction() {
+    let mut entries = readme_entry();
+    ClockCorrector::new(TimeMode::Offset, 3600).correct(&mut entries, Utc::now(), false);
+
+    assert_eq!(entries[0].timestamp_ms(), 1762066718000 + 3_600_000);
+}
+
+#[test]
+fn auto_mode_estimates_skew_from_fresh_entries() {
+    let mut corrector = ClockCorrector::new(TimeMode::Auto { round_secs: 900 }, 0);
+
+    // the initial history says nothing about the skew
+    let mut entries = readme_entry();
+    corrector.correct(&mut entries, utc("2025-11-05T12:00:00Z"), false);
+    assert_eq!(entries[0].corrected_timestamp, None);
+
+    // a fresh entry received 40 s after its (true) time, device clock 1 h behind
+    let mut entries = readme_entry();
+    corrector.correct(&mut entries, utc("2025-11-02T07:59:18Z"), true);
+    assert_eq!(
+        entries[0].get_iso8601_from_timestamp(),
+        "2025-11-02T07:58:38+00:00"
+    );
+}
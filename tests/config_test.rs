@@ -0,0 +1,479 @@
+use vilog::{config, config_env, mqtt};
+
+fn vars(list: &[(&str, &str)]) -> Vec<(String, String)> {
+    list.iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[test]
+fn env_overrides_keys_with_types() {
+    let mut table: toml::Table = toml::from_str(
+        r#"
+        [mqtt]
+        host = "127.0.0.1"
+        port = 1883
+        "#,
+    )
+    .unwrap();
+
+    let applied = config_env::apply_env_overrides::<config::AppConfig, _>(
+        &mut table,
+        vars(&[
+            ("VILOG_MQTT__HOST", "broker.example"),
+            ("VILOG_MQTT__PORT", "8883"),
+            ("VILOG_MQTT__PASSWORD", "123456"),
+            ("VILOG_MQTT__USERNAME", "\"1234\""),
+            ("VILOG_MQTT__CLIENT_ID", "true"),
+            ("VILOG_INFLUXDB__ENABLED", "true"),
+            ("VILOG_CONFIG", "ignored.toml"),
+            ("HOME", "/root"),
+        ]),
+    )
+    .unwrap();
+    assert_eq!(
+        applied,
+        vec![
+            "influxdb.enabled",
+            "mqtt.client_id",
+            "mqtt.host",
+            "mqtt.password",
+            "mqtt.port",
+            "mqtt.username"
+        ]
+    );
+
+    let cfg: config::AppConfig = toml::Value::Table(table).try_into().unwrap();
+    let mqtt = cfg.mqtt.unwrap();
+    assert_eq!(mqtt.host.as_deref(), Some("broker.example"));
+    assert_eq!(mqtt.port, Some(8883));
+    // numbers and bools are strings where the key is one
+    assert_eq!(mqtt.password.as_deref(), Some("123456"));
+    assert_eq!(mqtt.username.as_deref(), Some("1234"));
+    assert_eq!(mqtt.client_id.as_deref(), Some("true"));
+    assert_eq!(cfg.influxdb.unwrap().enabled, Some(true));
+}
+
+#[test]
+fn env_overrides_entries_of_array_tables() {
+    let mut table: toml::Table = toml::from_str(
+        r#"
+        [[ecus]]
+        ecuid = "HPMU"
+        address = "0x680"
+        "#,
+    )
+    .unwrap();
+
+    config_env::apply_env_overrides::<config::AppConfig, _>(
+        &mut table,
+        vars(&[
+            ("VILOG_ECUS__0__ADDRESS", "0x68C"),
+            ("VILOG_ECUS__1__ECUID", "EMCU"),
+            ("VILOG_ECUS__1__COMMANDS__0__INTERVAL_SECS", "60"),
+        ]),
+    )
+    .unwrap();
+    let cfg: config::AppConfig = toml::Value::Table(table.clone()).try_into().unwrap();
+    let ecus = cfg.ecus.unwrap();
+    assert_eq!(ecus[0].address.as_deref(), Some("0x68C"));
+    assert_eq!(ecus[1].ecuid.as_deref(), Some("EMCU"));
+    assert_eq!(
+        ecus[1].commands.as_ref().unwrap()[0].interval_secs,
+        Some(60)
+    );
+
+    let err = config_env::apply_env_overrides::<config::AppConfig, _>(
+        &mut table,
+        vars(&[("VILOG_ECUS__5__ADDRESS", "0x680")]),
+    )
+    .unwrap_err();
+    assert!(err.contains("'ecus' has 2 entries"), "{}", err);
+}
+
+#[test]
+fn env_overrides_of_array_tables_in_any_order() {
+    let mut names: Vec<(String, String)> = (0..11)
+        .map(|i| (format!("VILOG_ECUS__{}__ECUID", i), format!("ECU{}", i)))
+        .collect();
+    names.reverse();
+
+    let mut table = toml::Table::new();
+    config_env::apply_env_overrides::<config::AppConfig, _>(&mut table, names).unwrap();
+    let cfg: config::AppConfig = toml::Value::Table(table).try_into().unwrap();
+    let ecuids: Vec<String> = cfg
+        .ecus
+        .unwrap()
+        .into_iter()
+        .map(|e| e.ecuid.unwrap())
+        .collect();
+    let expected: Vec<String> = (0..11).map(|i| format!("ECU{}", i)).collect();
+    assert_eq!(ecuids, expected);
+}
+
+#[test]
+fn env_file_variant_reads_secret() {
+    let secret = std::env::temp_dir().join(format!("vilog-token-{}", std::process::id()));
+    std::fs::write(&secret, "s3cr3t\n").unwrap();
+
+    let mut table = toml::Table::new();
+    config_env::apply_env_overrides::<config::AppConfig, _>(
+        &mut table,
+        vars(&[("VILOG_INFLUXDB__TOKEN_FILE", secret.to_str().unwrap())]),
+    )
+    .unwrap();
+    std::fs::remove_file(&secret).unwrap();
+
+    let cfg: config::AppConfig = toml::Value::Table(table).try_into().unwrap();
+    assert_eq!(cfg.influxdb.unwrap().token.as_deref(), Some("s3cr3t"));
+
+    let missing = config_env::apply_env_overrides::<config::AppConfig, _>(
+        &mut toml::Table::new(),
+        vars(&[("VILOG_INFLUXDB__TOKEN_FILE", "/nonexistent/token")]),
+    );
+    assert!(missing.is_err());
+}
+
+#[test]
+fn secrets_are_redacted_in_debug_output() {
+    let cfg: config::AppConfig = toml::from_str(
+        r#"
+        [mqtt]
+        password = "mqtt-secret"
+        [influxdb]
+        token = "influx-secret"
+        password = "influx-password"
+        "#,
+    )
+    .unwrap();
+    let resolved = config::resolve_influx(cfg.influxdb.as_ref());
+
+    let printed = format!("{:?} {:?}", cfg, resolved);
+    assert!(!printed.contains("secret"), "{}", printed);
+    assert!(!printed.contains("influx-password"), "{}", printed);
+    assert!(printed.contains("***"));
+}
+
+#[test]
+fn tls_defaults_to_port_8883_and_checks_files() {
+    let cfg: config::AppConfig = toml::from_str(
+        r#"
+        [mqtt]
+        host = "broker.example"
+        tls = true
+        alpn = ["mqtt"]
+        "#,
+    )
+    .unwrap();
+    let mqtt = config::resolve_mqtt(cfg.mqtt.as_ref()).unwrap();
+    assert_eq!((mqtt.host.as_str(), mqtt.port), ("broker.example", 8883));
+
+    let plain = config::resolve_mqtt(None).unwrap();
+    assert_eq!((plain.host.as_str(), plain.port), ("127.0.0.1", 1883));
+
+    let cfg: config::AppConfig = toml::from_str(
+        r#"
+        [mqtt]
+        tls = true
+        ca_path = "/nonexistent/ca.pem"
+        "#,
+    )
+    .unwrap();
+    let err = config::resolve_mqtt(cfg.mqtt.as_ref()).unwrap_err();
+    assert!(err.to_string().contains("/nonexistent/ca.pem"));
+
+    let cfg: config::AppConfig = toml::from_str(
+        r#"
+        [mqtt]
+        tls = true
+        client_cert_path = "client.pem"
+        "#,
+    )
+    .unwrap();
+    assert!(config::resolve_mqtt(cfg.mqtt.as_ref()).is_err());
+}
+
+#[test]
+fn protocol_and_websocket_transport() {
+    let plain = config::resolve_mqtt(None).unwrap();
+    assert_eq!(plain.protocol, mqtt::MqttProtocol::V5);
+    assert!(matches!(plain.transport, rumqttc::Transport::Tcp));
+
+    let cfg: config::AppConfig = toml::from_str(
+        r#"
+        [mqtt]
+        host = "ws://proxy.example:8080/mqtt"
+        protocol = "v311"
+        "#,
+    )
+    .unwrap();
+    let mqtt = config::resolve_mqtt(cfg.mqtt.as_ref()).unwrap();
+    assert_eq!(mqtt.protocol, mqtt::MqttProtocol::V311);
+    assert!(matches!(mqtt.transport, rumqttc::Transport::Ws));
+
+    let cfg: config::AppConfig = toml::from_str(
+        r#"
+        [mqtt]
+        host = "wss://proxy.example/mqtt"
+        ca_path = "/nonexistent/ca.pem"
+        "#,
+    )
+    .unwrap();
+    let err = config::resolve_mqtt(cfg.mqtt.as_ref()).unwrap_err();
+    assert!(err.to_string().contains("/nonexistent/ca.pem"));
+
+    let cfg: config::AppConfig = toml::from_str(
+        r#"
+        [mqtt]
+        host = "ws://proxy.example/mqtt"
+        tls = true
+        "#,
+    )
+    .unwrap();
+    assert!(config::resolve_mqtt(cfg.mqtt.as_ref()).is_err());
+}
+
+#[test]
+fn qos_and_persistent_session() {
+    let plain = config::resolve_mqtt(None).unwrap();
+    assert_eq!(plain.subscribe_qos, rumqttc::QoS::AtMostOnce);
+    assert_eq!(plain.command_qos, rumqttc::QoS::ExactlyOnce);
+    assert!(!plain.persistent_session);
+
+    let cfg: config::AppConfig = toml::from_str(
+        r#"
+        [mqtt]
+        subscribe_qos = 1
+        command_qos = 7
+        persistent_session = true
+        session_expiry_secs = 600
+        "#,
+    )
+    .unwrap();
+    let mqtt = config::resolve_mqtt(cfg.mqtt.as_ref()).unwrap();
+    assert_eq!(mqtt.subscribe_qos, rumqttc::QoS::AtLeastOnce);
+    // invalid levels fall back to the default
+    assert_eq!(mqtt.command_qos, rumqttc::QoS::ExactlyOnce);
+    assert!(mqtt.persistent_session);
+    assert_eq!(mqtt.session_expiry_secs, 600);
+}
+
+fn write_temp(name: &str, content: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("vilog-{}-{}.toml", name, std::process::id()));
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+#[test]
+fn shipped_configs_pass_strict_check() {
+    for path in ["vilog.toml", "vilog_VCMU.toml"] {
+        let cfg = config::load_app_config(path, true);
+        assert!(matches!(cfg, Ok(Some(_))), "{}: {:?}", path, cfg.err());
+    }
+}
+
+#[test]
+fn unknown_keys_are_errors_in_strict_mode() {
+    let path = write_temp("unknown", "[mqtt]\nport = 1883\nhots = \"broker\"\n");
+    let strict = config::load_app_config(path.to_str().unwrap(), true);
+    let lenient = config::load_app_config(path.to_str().unwrap(), false);
+    std::fs::remove_file(&path).unwrap();
+
+    let err = strict.unwrap_err();
+    assert!(
+        err.contains("line 3, column 1: mqtt.hots: unknown key"),
+        "{}",
+        err
+    );
+    assert_eq!(lenient.unwrap().unwrap().mqtt.unwrap().port, Some(1883));
+}
+
+#[test]
+fn broken_values_are_rejected() {
+    let path = write_temp(
+        "values",
+        r#"
+[topics]
+command_payload = "{\"mode\": \"read-json\", "
+command_interval_secs = 0
+
+[influxdb]
+url = "not a url"
+"#,
+    );
+    let err = config::load_app_config(path.to_str().unwrap(), false).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(err.contains("line 3, column 1: topics.command_payload: invalid JSON"));
+    assert!(err.contains("line 4, column 1: topics.command_interval_secs: must be above zero"));
+    assert!(err.contains("line 7, column 1: influxdb.url: invalid URL"));
+}
+
+#[test]
+fn ecus_derive_topics_and_commands_from_the_address() {
+    let cfg: config::AppConfig = toml::from_str(
+        r#"
+        [topics]
+        command_interval_secs = 120
+
+        [[ecus]]
+        address = "0x6a1"
+        [[ecus.commands]]
+        dids = [266, 264]
+        jitter_secs = 5
+
+        [[ecus]]
+        ecuid = "EMCU"
+        address = "0x6c3"
+        "#,
+    )
+    .unwrap();
+    let topics = config::resolve_topics(cfg.topics.as_ref(), cfg.ecus.as_deref(), false);
+
+    let vcmu = &topics.ecus[0];
+    assert_eq!(vcmu.ecuid, "6A1");
+    assert_eq!(vcmu.error, "open3e/6a1_266_ErrorDtcHistory");
+    assert_eq!(vcmu.status, "open3e/6a1_258_StatusDtcHistory");
+    assert_eq!(vcmu.commands.len(), 1);
+    let command = &vcmu.commands[0];
+    assert_eq!((command.interval_secs, command.jitter_secs), (120, 5));
+    let payload: serde_json::Value = serde_json::from_str(&command.payload).unwrap();
+    assert_eq!(
+        payload,
+        serde_json::json!({"mode": "read-json", "data": [266, 264], "addr": "0x6a1"})
+    );
+
+    assert_eq!(topics.ecus[1].ecuid, "EMCU");
+    assert_eq!(
+        topics.ecus[1].commands[0].dids,
+        vec![266, 264, 262, 260, 258]
+    );
+    assert_eq!(
+        topics
+            .ecu_for_topic("open3e/6c3_262_ServiceDtcHistory")
+            .map(|e| e.ecuid.as_str()),
+        Some("EMCU")
+    );
+
+    // without [[ecus]] the [topics] section is the only ECU
+    let single = config::resolve_topics(None, None, false);
+    assert_eq!(single.ecus.len(), 1);
+    assert_eq!(
+        single.ecus[0].commands[0].dids,
+        vec![258, 260, 262, 264, 266]
+    );
+    assert_eq!(single.subscribed_topics().len(), 5);
+    assert!(
+        single
+            .ecu_for_active_errors("open3e/680_265_ErrorDtcList")
+            .is_none()
+    );
+}
+
+#[test]
+fn active_errors_are_read_only_for_the_api() {
+    let with_api = config::resolve_topics(None, None, true);
+    assert_eq!(
+        with_api.ecus[0].commands[0].dids,
+        vec![258, 260, 262, 264, 265, 266]
+    );
+    assert!(
+        with_api
+            .subscribed_topics()
+            .contains(&"open3e/680_265_ErrorDtcList")
+    );
+
+    let cfg: config::AppConfig = toml::from_str(
+        r#"
+        [api]
+        enabled = true
+        [[ecus]]
+        address = "0x6c3"
+        "#,
+    )
+    .unwrap();
+    let topics = config::resolve_topics(cfg.topics.as_ref(), cfg.ecus.as_deref(), true);
+    assert_eq!(
+        topics.ecus[0].commands[0].dids,
+        vec![266, 264, 262, 260, 258, 265]
+    );
+    assert_eq!(
+        topics
+            .ecu_for_active_errors("open3e/6c3_265_ErrorDtcList")
+            .map(|e| e.ecuid.as_str()),
+        Some("6C3")
+    );
+}
+
+#[test]
+fn ecus_with_the_same_topics_are_rejected() {
+    let path = write_temp(
+        "ecus",
+        "[[ecus]]\necuid = \"A\"\n\n[[ecus]]\necuid = \"B\"\n\n[[ecus.commands]]\ninterval_secs = 0\n",
+    );
+    let err = config::load_app_config(path.to_str().unwrap(), true).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(
+        err.contains("ecus.1.commands.0.interval_secs: must be above zero"),
+        "{}",
+        err
+    );
+    assert!(
+        err.contains("line 4, column 1: ecus.1: history topic"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn influx_schema_is_checked() {
+    let path = write_temp(
+        "schema",
+        r#"[influxdb]
+tags = ["ecuid", "colour"]
+fields = ["ecuid", "suspect"]
+static_tags = { ecuid = "x", site = "" }
+
+[[ecus]]
+ecuid = "HPMU"
+static_tags = { location = "cellar" }
+"#,
+    );
+    let err = config::load_app_config(path.to_str().unwrap(), false).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(
+        err.contains("line 2, column 1: influxdb.tags: unknown attribute 'colour'"),
+        "{}",
+        err
+    );
+    assert!(
+        err.contains("line 3, column 1: influxdb.fields: 'ecuid' is a tag already"),
+        "{}",
+        err
+    );
+    assert!(err.contains("influxdb.fields: needs an attribute besides suspect"));
+    assert!(err.contains("influxdb.static_tags.ecuid: 'ecuid' is written from the entry"));
+    assert!(err.contains("influxdb.static_tags.site: must not be empty"));
+    assert!(!err.contains("ecus.0.static_tags"));
+
+    // without fields, a tag must not be one of the default fields
+    let path = write_temp("schema_tags", "[influxdb]\ntags = [\"ecuid\", \"text\"]\n");
+    let err = config::load_app_config(path.to_str().unwrap(), false).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+    assert!(
+        err.contains("line 2, column 1: influxdb.tags: 'text' is a field by default"),
+        "{}",
+        err
+    );
+
+    let cfg: config::AppConfig =
+        toml::from_str("[[ecus]]\necuid = \"HPMU\"\nstatic_tags = { location = \"cellar\" }\n")
+            .unwrap();
+    let topics = config::resolve_topics(cfg.topics.as_ref(), cfg.ecus.as_deref(), false);
+    assert_eq!(
+        topics.ecus[0].static_tags,
+        [("location".to_string(), "cellar".to_string())]
+    );
+}
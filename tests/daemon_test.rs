@@ -0,0 +1,155 @@
+use chrono::Utc;
+use tokio::time;
+
+use vilog::api;
+use vilog::config;
+use vilog::daemon::Daemon;
+
+const STATUS_TOPIC: &str = "open3e/680_258_StatusDtcHistory";
+
+fn app_config(extra: &str) -> config::AppConfig {
+    toml::from_str(&format!(
+        r#"
+        [mqtt]
+        host = "broker.example"
+
+        [influxdb]
+        enabled = false
+
+        {}
+        "#,
+        extra
+    ))
+    .unwrap()
+}
+
+fn testdata(name: &str) -> String {
+    std::fs::read_to_string(format!("tests/testdata/{}.json", name)).unwrap()
+}
+
+#[tokio::test]
+async fn subscribes_on_the_first_connection_and_without_a_session() {
+    let mut daemon = Daemon::new(app_config(""));
+
+    let topics = daemon.connected(true).unwrap();
+    assert_eq!(topics.len(), 5);
+    assert!(topics.contains(&STATUS_TOPIC.to_string()));
+    // the broker kept the subscriptions
+    assert_eq!(daemon.connected(true), None);
+    assert_eq!(daemon.connected(false).map(|t| t.len()), Some(5));
+}
+
+#[tokio::test]
+async fn reload_applies_live_changes_and_keeps_restart_changes() {
+    let mut daemon = Daemon::new(app_config(""));
+    daemon
+        .handle_message(
+            STATUS_TOPIC,
+            testdata("258_1").as_bytes(),
+            false,
+            Utc::now(),
+        )
+        .await;
+    assert!(daemon.processor().last_list(STATUS_TOPIC).is_some());
+
+    daemon
+        .reload(
+            toml::from_str(
+                r#"
+                [mqtt]
+                host = "other.example"
+
+                [influxdb]
+                enabled = false
+
+                [topics]
+                ecuid = "VCMU"
+                "#,
+            )
+            .unwrap(),
+        )
+        .await;
+    assert_eq!(daemon.topics().ecus[0].ecuid, "VCMU");
+    assert_eq!(
+        daemon.config().mqtt.as_ref().unwrap().host.as_deref(),
+        Some("broker.example")
+    );
+    // the lists survive the reload
+    assert!(daemon.processor().last_list(STATUS_TOPIC).is_some());
+}
+
+#[tokio::test]
+async fn shutdown_saves_the_state_for_the_next_start() {
+    let path = std::env::temp_dir().join(format!("vilog-daemon-{}.json", std::process::id()));
+    let cfg = app_config(&format!(
+        "[state]\nenabled = true\npath = {:?}\n",
+        path.to_str().unwrap()
+    ));
+
+    let mut daemon = Daemon::new(cfg.clone());
+    daemon
+        .handle_message(
+            STATUS_TOPIC,
+            testdata("258_1").as_bytes(),
+            false,
+            Utc::now(),
+        )
+        .await;
+    let deadline = time::Instant::now() + daemon.shutdown_timeout();
+    assert!(daemon.shutdown(deadline).await);
+
+    let restarted = Daemon::new(cfg);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        restarted
+            .processor()
+            .last_list(STATUS_TOPIC)
+            .unwrap()
+            .list_entries,
+        daemon
+            .processor()
+            .last_list(STATUS_TOPIC)
+            .unwrap()
+            .list_entries
+    );
+}
+
+#[tokio::test]
+async fn active_errors_reach_the_api() {
+    let mut daemon = Daemon::new(app_config(
+        "[api]\nenabled = true\nlisten = \"127.0.0.1:0\"\n",
+    ));
+    daemon.start_api("broker.example").await;
+    assert!(
+        daemon
+            .connected(false)
+            .unwrap()
+            .contains(&"open3e/680_265_ErrorDtcList".to_string())
+    );
+
+    daemon
+        .handle_message(
+            "open3e/680_265_ErrorDtcList",
+            br#"{"Count": 1, "ListEntries": [{"Error": {"ID": 221, "Text": "DomesticHotWaterSensor"}}]}"#,
+            false,
+            Utc::now(),
+        )
+        .await;
+    daemon
+        .handle_message(
+            STATUS_TOPIC,
+            testdata("258_1").as_bytes(),
+            false,
+            Utc::now(),
+        )
+        .await;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/ecus", listener.local_addr().unwrap());
+    let app = api::router(daemon.api_state().unwrap().clone(), None);
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    let body = reqwest::get(&url).await.unwrap().text().await.unwrap();
+    let ecus: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(ecus[0]["active_faults"][0]["code"], "F.221");
+    assert!(ecus[0]["last_poll"].is_string());
+}
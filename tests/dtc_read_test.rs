@@ -1,8 +1,7 @@
-#[path = "../src/dtc.rs"]
-mod dtc;
-
 use std::fs;
 
+use vilog::dtc;
+
 #[test]
 fn read_258_1_json_into_response_dtc() {
     let json_path = "tests/testdata/258_1.json";
@@ -103,3 +102,100 @@ fn diff_new_entries_between_258_1_and_258_3() {
     assert_eq!(e2.date_time.date_time, "2025-11-10 11:17:24");
     assert_eq!(e2.date_time.timestamp, 1762769844000);
 }
+
+fn read_response(name: &str) -> dtc::ResponseDtc {
+    let data = fs::read_to_string(format!("tests/testdata/{}.json", name))
+        .expect("failed to read test JSON file");
+    serde_json::from_str(&data).expect("failed to parse test JSON file")
+}
+
+#[test]
+fn detect_gap_between_polls() {
+    let first = read_response("258_1");
+    let second = read_response("258_2");
+    let third = read_response("258_3");
+
+    // one new entry, the rest overlaps with the last poll
+    assert_eq!(dtc::detect_gap(&first, &second, 1), None);
+    // the first poll can not tell anything
+    assert_eq!(
+        dtc::detect_gap(&dtc::ResponseDtc::new_empty(), &first, 10),
+        None
+    );
+
+    // nothing of the last poll is left in the list
+    let mut newest_only = dtc::ResponseDtc::new_empty();
+    newest_only.list_entries.push(third.list_entries[0].clone());
+    let diff = dtc::list_entries_new_not_in_old(&newest_only, &first);
+    assert_eq!(
+        dtc::detect_gap(&newest_only, &first, diff.len()),
+        Some(dtc::Gap::AllNew { entries: 10 })
+    );
+
+    // GrandTotal counts more events than the list shows as new
+    let (mut old, mut new) = (first.clone(), second.clone());
+    old.grand_total = Some(100);
+    new.grand_total = Some(101);
+    assert_eq!(dtc::detect_gap(&old, &new, 1), None);
+    new.grand_total = Some(105);
+    assert_eq!(
+        dtc::detect_gap(&old, &new, 1),
+        Some(dtc::Gap::GrandTotal {
+            increase: 5,
+            new_entries: 1
+        })
+    );
+}
+
+fn list(entries: &[(&str, i64, i64)]) -> dtc::ResponseDtc {
+    let entries: Vec<String> = entries
+        .iter()
+        .map(|(kind, id, timestamp)| {
+            format!(
+                r#"{{"{}":{{"ID":{},"Text":"T{}"}},"DateTime":{{"DateTime":"","Timestamp":{}}},"Unknown":0}}"#,
+                kind, id, id, timestamp
+            )
+        })
+        .collect();
+    let json = format!(
+        r#"{{"Count":{},"ListEntries":[{}]}}"#,
+        entries.len(),
+        entries.join(",")
+    );
+    serde_json::from_str(&json).unwrap()
+}
+
+#[test]
+fn diff_counts_identical_entries_and_respects_the_kind() {
+    let old = list(&[("Error", 7, 2000), ("Error", 7, 1000), ("Error", 7, 1000)]);
+    let new = list(&[
+        ("Warning", 7, 2000),
+        ("Error", 7, 2000),
+        ("Error", 7, 1000),
+        ("Error", 7, 1000),
+        ("Error", 7, 1000),
+    ]);
+
+    let diff = dtc::list_entries_new_not_in_old(&old, &new);
+    let keys: Vec<(&str, i64, u32)> = diff
+        .iter()
+        .map(|e| (e.state_type.as_str(), e.date_time.timestamp, e.occurrence))
+        .collect();
+    // the warning has the same ID and time as the error, but is another event;
+    // the third identical error is new
+    assert_eq!(keys, vec![("Warning", 2000, 0), ("Error", 1000, 2)]);
+    assert_ne!(new.list_entries[0], new.list_entries[1]);
+}
+
+#[test]
+fn event_ids_are_stable_and_distinct() {
+    let new = list(&[("Error", 7, 1000), ("Error", 7, 1000)]);
+    let diff = dtc::list_entries_new_not_in_old(&dtc::ResponseDtc::new_empty(), &new);
+    let ids: Vec<String> = diff.iter().map(|e| e.event_id("250A", "HPMU")).collect();
+
+    assert_eq!(ids, vec!["aea58483e18688ac", "aea58583e1868a5f"]);
+    assert_ne!(
+        diff[0].event_id("250A", "HPMU"),
+        diff[0].event_id("250A", "VCMU")
+    );
+}
@@ -0,0 +1,77 @@
+use vilog::format::OutputFormat;
+use vilog::{dtc, event};
+
+fn sample_record() -> event::EventRecord {
+    let data =
+        std::fs::read_to_string("tests/testdata/264_1.json").expect("failed to read 264_1.json");
+    let parsed: dtc::ResponseDtc = serde_json::from_str(&data).expect("failed to parse 264_1.json");
+    event::EventRecord::new(&parsed.list_entries[0], "250A", "HPMU")
+}
+
+#[test]
+fn human_format_matches_classic_line() {
+    let line = OutputFormat::Human.format(&sample_record());
+    assert_eq!(
+        line,
+        "2025-11-09T17:50:06+00:00 (2025-11-09 18:50:06) 250A HPMU[100]: Warning warning A.100 RestoreEepromToDefault event_id=769a61e21c74e6a9"
+    );
+}
+
+#[test]
+fn json_format_has_all_fields() {
+    let line = OutputFormat::Json.format(&sample_record());
+    let v: serde_json::Value = serde_json::from_str(&line).expect("output is not JSON");
+
+    assert_eq!(v["systemid"], "250A");
+    assert_eq!(v["ecuid"], "HPMU");
+    assert_eq!(v["type"], "Warning");
+    assert_eq!(v["severity"], "warning");
+    assert_eq!(v["code"], "A.100");
+    assert_eq!(v["id"], 100);
+    assert_eq!(v["text"], "RestoreEepromToDefault");
+    assert_eq!(v["timestamp"], 1762710606000i64);
+    assert_eq!(v["date_time"], "2025-11-09 18:50:06");
+    assert_eq!(v["unknown"], 566);
+    assert_eq!(v["event_id"], "769a61e21c74e6a9");
+}
+
+#[test]
+fn logfmt_quotes_values_with_spaces() {
+    let line = OutputFormat::Logfmt.format(&sample_record());
+    assert!(line.starts_with(
+        "time=2025-11-09T17:50:06+00:00 timestamp=1762710606000 date_time=\"2025-11-09 18:50:06\""
+    ));
+    assert!(line.ends_with(
+        "text=RestoreEepromToDefault unknown=566 suspect=false event_id=769a61e21c74e6a9"
+    ));
+}
+
+#[test]
+fn csv_has_header_and_quotes() {
+    let format = OutputFormat::Csv;
+    assert_eq!(
+        format.header().unwrap(),
+        "time,timestamp,date_time,systemid,ecuid,type,severity,code,id,text,unknown,suspect,event_id"
+    );
+
+    let mut record = sample_record();
+    record.text = "a, \"quoted\" text".to_string();
+    assert!(
+        format
+            .format(&record)
+            .ends_with(",A.100,100,\"a, \"\"quoted\"\" text\",566,false,769a61e21c74e6a9")
+    );
+}
+
+#[test]
+fn template_replaces_known_fields() {
+    let format = OutputFormat::parse("template", Some("{code} {text} @{ecuid}")).unwrap();
+    assert_eq!(
+        format.format(&sample_record()),
+        "A.100 RestoreEepromToDefault @HPMU"
+    );
+
+    assert!(OutputFormat::parse("template", Some("{nope}")).is_err());
+    assert!(OutputFormat::parse("template", None).is_err());
+    assert!(OutputFormat::parse("xml", None).is_err());
+}
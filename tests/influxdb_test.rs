@@ -0,0 +1,216 @@
+use vilog::{config, influxdb};
+
+fn influx_from_toml(raw: &str) -> config::InfluxResolved {
+    let cfg: config::InfluxConfig = toml::from_str(raw).expect("invalid test config");
+    config::resolve_influx(Some(&cfg))
+}
+
+#[test]
+fn v2_write_url_is_the_default() {
+    let influx = influx_from_toml(
+        r#"
+        url = "http://influx:8086/"
+        org = "home"
+        bucket = "heatpump"
+        "#,
+    );
+    let (url, query) = influxdb::write_url(&influx);
+
+    assert_eq!(url, "http://influx:8086/api/v2/write");
+    assert_eq!(
+        query,
+        vec![
+            ("org", "home".to_string()),
+            ("bucket", "heatpump".to_string()),
+            ("precision", "ms".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn v1_write_url_with_retention_policy_and_query_credentials() {
+    let influx = influx_from_toml(
+        r#"
+        api_version = "v1"
+        url = "http://influx:8086"
+        database = "vilog"
+        retention_policy = "one_year"
+        username = "writer"
+        password = "secret"
+        credentials_in_query = true
+        "#,
+    );
+    let (url, query) = influxdb::write_url(&influx);
+
+    assert_eq!(url, "http://influx:8086/write");
+    assert_eq!(
+        query,
+        vec![
+            ("db", "vilog".to_string()),
+            ("precision", "ms".to_string()),
+            ("rp", "one_year".to_string()),
+            ("u", "writer".to_string()),
+            ("p", "secret".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn v3_write_url_uses_database() {
+    let influx = influx_from_toml(
+        r#"
+        api_version = "v3"
+        url = "https://influx3.example"
+        database = "site1"
+        "#,
+    );
+    let (url, query) = influxdb::write_url(&influx);
+
+    assert_eq!(url, "https://influx3.example/api/v3/write_lp");
+    assert_eq!(
+        query,
+        vec![
+            ("db", "site1".to_string()),
+            ("precision", "millisecond".to_string()),
+        ]
+    );
+}
+
+type ReceivedRequests = Vec<(String, Vec<u8>)>;
+
+/// Minimal HTTP server that answers every request with 204 and returns the
+/// (header, body) pairs it received.
+fn spawn_recording_server(requests: usize) -> (String, std::thread::JoinHandle<ReceivedRequests>) {
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = std::thread::spawn(move || {
+        let mut received = Vec::new();
+        for _ in 0..requests {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut head = String::new();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(v) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    content_length = v.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+                head.push_str(&line);
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            received.push((head, body));
+        }
+        received
+    });
+    (url, handle)
+}
+
+#[tokio::test]
+async fn writer_batches_lines_and_compresses() {
+    use std::io::Read;
+
+    let (url, server) = spawn_recording_server(2);
+    let influx = influx_from_toml(&format!(
+        r#"
+        enabled = true
+        url = "{}"
+        batch_size = 2
+        flush_interval_ms = 60000
+        gzip = true
+        "#,
+        url
+    ));
+
+    let writer = influxdb::InfluxWriter::start(reqwest::Client::new(), std::sync::Arc::new(influx));
+    writer
+        .write(vec![
+            "m a=1i 1".to_string(),
+            "m a=2i 2".to_string(),
+            "m a=3i 3".to_string(),
+        ])
+        .await;
+    assert!(writer.flush().await);
+
+    let received = server.join().unwrap();
+    let mut bodies: Vec<String> = received
+        .iter()
+        .map(|(head, body)| {
+            assert!(head.to_ascii_lowercase().contains("content-encoding: gzip"));
+            let mut out = String::new();
+            flate2::read::GzDecoder::new(body.as_slice())
+                .read_to_string(&mut out)
+                .unwrap();
+            out
+        })
+        .collect();
+    // both batches may be in flight at the same time
+    bodies.sort();
+    assert_eq!(bodies, vec!["m a=1i 1\nm a=2i 2\n", "m a=3i 3\n"]);
+}
+
+#[tokio::test]
+async fn flush_reports_failed_writes_once() {
+    // nothing listens on this port any more
+    let url = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    };
+    let influx = influx_from_toml(&format!("enabled = true\nurl = \"{}\"", url));
+
+    let writer = influxdb::InfluxWriter::start(reqwest::Client::new(), std::sync::Arc::new(influx));
+    writer.write(vec!["m a=1i 1".to_string()]).await;
+    assert!(!writer.flush().await);
+    assert_eq!(writer.status().lock().unwrap().ok(), Some(false));
+    // nothing was written since
+    assert!(writer.flush().await);
+}
+
+#[test]
+fn configured_schema_writes_typed_fields_and_static_tags() {
+    let influx = influx_from_toml(
+        r#"
+        tags = ["ecuid", "code", "systemid"]
+        fields = ["id", "unknown", "text", "datetime", "code"]
+        static_tags = { location = "cellar", customer = "acme" }
+        "#,
+    );
+    // code is a tag already
+    assert_eq!(
+        influx.schema.fields,
+        [
+            influxdb::InfluxAttr::Id,
+            influxdb::InfluxAttr::Unknown,
+            influxdb::InfluxAttr::Text,
+            influxdb::InfluxAttr::DateTime,
+        ]
+    );
+
+    let list: vilog::dtc::ResponseDtc = serde_json::from_str(
+        r#"{"Count": 1, "ListEntries": [{"Error": {"ID": 454, "Text": "CompressorHighPressure"},
+            "DateTime": {"DateTime": "2025-11-10 10:12:29", "Timestamp": 1762765949000},
+            "Unknown": 1}]}"#,
+    )
+    .unwrap();
+    let ecu_tags = [("location".to_string(), "heat pump room".to_string())];
+
+    // the ECU's location wins, the empty systemid is left out
+    assert_eq!(
+        influx
+            .schema
+            .line("syslog", "", "HPMU", &ecu_tags, &list.list_entries[0]),
+        "syslog,ecuid=HPMU,code=F.454,customer=acme,location=heat\\ pump\\ room \
+         id=454i,unknown=1i,text=\"CompressorHighPressure\",datetime=\"2025-11-10 10:12:29\" \
+         1762765949000"
+    );
+}
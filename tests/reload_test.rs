@@ -0,0 +1,78 @@
+use vilog::{config, reload};
+
+const RUNNING: &str = r#"
+[mqtt]
+host = "127.0.0.1"
+
+[topics]
+error = "open3e/680_266_ErrorDtcHistory"
+command_interval_secs = 60
+
+[output]
+format = "human"
+"#;
+
+#[test]
+fn live_changes_are_applied_and_restart_changes_kept() {
+    let running: config::AppConfig = toml::from_str(RUNNING).unwrap();
+    let running_topics =
+        config::resolve_topics(running.topics.as_ref(), running.ecus.as_deref(), false);
+
+    let new: config::AppConfig = toml::from_str(
+        r#"
+        [mqtt]
+        host = "broker.example"
+
+        [topics]
+        error = "other/266"
+        systemid = "other"
+        command_interval_secs = 30
+
+        [output]
+        format = "json"
+        "#,
+    )
+    .unwrap();
+
+    let (merged, topics, changes) = reload::merge(&running, &running_topics, new);
+    assert_eq!(changes.live(), vec!["topics", "output"]);
+    assert_eq!(
+        changes.restart,
+        vec!["mqtt", "topics/ecus (subscribed topics)"]
+    );
+
+    // MQTT and the ECUs with their subscriptions stay as they are until a restart
+    assert_eq!(merged.mqtt, running.mqtt);
+    assert_eq!(topics.ecus, running_topics.ecus);
+    assert_eq!(topics.systemid, "other");
+    assert_eq!(merged.output.unwrap().format.as_deref(), Some("json"));
+
+    // without a change of the history topics the new schedule is applied live
+    let new: config::AppConfig = toml::from_str(
+        &RUNNING.replace("command_interval_secs = 60", "command_interval_secs = 30"),
+    )
+    .unwrap();
+    let (_, topics, changes) = reload::merge(&running, &running_topics, new);
+    assert!(changes.restart.is_empty());
+    assert_eq!(changes.live(), vec!["topics"]);
+    assert_eq!(topics.ecus[0].commands[0].interval_secs, 30);
+}
+
+#[test]
+fn unchanged_config_reports_nothing() {
+    let running: config::AppConfig = toml::from_str(RUNNING).unwrap();
+    let running_topics =
+        config::resolve_topics(running.topics.as_ref(), running.ecus.as_deref(), false);
+
+    let (_, _, changes) = reload::merge(&running, &running_topics, running.clone());
+    assert_eq!(changes, reload::Changes::default());
+}
+
+#[test]
+fn broken_config_file_is_an_error() {
+    let path = std::env::temp_dir().join(format!("vilog-reload-{}.toml", std::process::id()));
+    std::fs::write(&path, "[mqtt\nhost = ").unwrap();
+    let result = config::load_app_config(path.to_str().unwrap(), false);
+    std::fs::remove_file(&path).unwrap();
+    assert!(result.is_err());
+}
@@ -0,0 +1,193 @@
+use std::path::Path;
+
+use vilog::clock::TimeMode;
+use vilog::state::DedupState;
+use vilog::{config, processor, replay};
+
+#[test]
+fn load_directory_orders_by_cycle_then_did() {
+    let topics = config::resolve_topics(None, None, false);
+    let records = replay::load_records(Path::new("tests/testdata"), &topics)
+        .expect("failed to load tests/testdata");
+
+    let ecu = &topics.ecus[0];
+    let order: Vec<&str> = records.iter().map(|r| r.topic.as_str()).collect();
+    assert_eq!(
+        order,
+        vec![
+            ecu.status.as_str(),
+            ecu.info.as_str(),
+            ecu.service.as_str(),
+            ecu.warning.as_str(),
+            ecu.error.as_str(),
+            ecu.status.as_str(),
+            ecu.status.as_str(),
+        ]
+    );
+    assert!(records.iter().all(|r| r.received_at.is_none()));
+}
+
+#[test]
+fn replayed_jsonl_goes_through_the_diff() {
+    let topics = config::resolve_topics(None, None, false);
+    let records = replay::load_records(Path::new("tests/testdata/capture_1.jsonl"), &topics)
+        .expect("failed to load capture_1.jsonl");
+    assert_eq!(records.len(), 3);
+    assert!(records[0].received_at.is_some());
+
+    let mut processor = processor::DtcProcessor::new(
+        topics,
+        &config::resolve_validation(None),
+        &config::resolve_time(None),
+    );
+    let counts: Vec<usize> = records
+        .iter()
+        .map(|r| {
+            processor
+                .process(&r.topic, &r.payload, r.received_at.unwrap())
+                .unwrap()
+                .len()
+        })
+        .collect();
+
+    // full history first, nothing on the empty error list, then only the new entry
+    assert_eq!(counts, vec![10, 0, 1]);
+}
+
+#[test]
+fn unknown_topics_are_ignored() {
+    let mut processor = processor::DtcProcessor::new(
+        config::resolve_topics(None, None, false),
+        &config::resolve_validation(None),
+        &config::resolve_time(None),
+    );
+    let diff = processor
+        .process(
+            "open3e/somethingelse",
+            "not json at all",
+            chrono::Utc::now(),
+        )
+        .expect("unknown topics must not be parsed");
+    assert!(diff.is_empty());
+}
+
+#[test]
+fn last_list_keeps_the_corrected_times() {
+    let mut processor = processor::DtcProcessor::new(
+        config::resolve_topics(None, None, false),
+        &config::resolve_validation(None),
+        &config::TimeResolved {
+            mode: TimeMode::Offset,
+            offset_secs: 60,
+        },
+    );
+    let topic = "open3e/680_258_StatusDtcHistory";
+    let first = std::fs::read_to_string("tests/testdata/258_1.json").unwrap();
+    let second = std::fs::read_to_string("tests/testdata/258_2.json").unwrap();
+
+    let mut emitted = processor
+        .process(topic, &first, chrono::Utc::now())
+        .unwrap();
+    emitted.extend(
+        processor
+            .process(topic, &second, chrono::Utc::now())
+            .unwrap(),
+    );
+    let list = &processor.last_list(topic).unwrap().list_entries;
+    // also the entries carried over from the first list
+    for entry in list {
+        let same = emitted
+            .iter()
+            .find(|e| *e == entry && e.occurrence == entry.occurrence)
+            .unwrap();
+        assert_eq!(entry.timestamp_ms(), same.timestamp_ms());
+        assert_eq!(entry.timestamp_ms(), entry.date_time.timestamp + 60_000);
+    }
+}
+
+#[test]
+fn saved_state_suppresses_known_entries_after_a_restart() {
+    let new_processor = || {
+        processor::DtcProcessor::new(
+            config::resolve_topics(None, None, false),
+            &config::resolve_validation(None),
+            &config::resolve_time(None),
+        )
+    };
+    let topic = "open3e/680_258_StatusDtcHistory";
+    let first = std::fs::read_to_string("tests/testdata/258_1.json").unwrap();
+    let second = std::fs::read_to_string("tests/testdata/258_2.json").unwrap();
+
+    let mut before = new_processor();
+    before.process(topic, &first, chrono::Utc::now()).unwrap();
+    let mut state = before.dedup_state();
+    state
+        .lists
+        .insert("open3e/unknown".to_string(), state.lists[topic].clone());
+
+    let path = std::env::temp_dir().join(format!("vilog-state-{}.json", std::process::id()));
+    state.save(&path).unwrap();
+    let loaded = DedupState::load(&path).unwrap().unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let mut after = new_processor();
+    assert_eq!(after.restore(loaded), 1);
+    // equal entries have the same kind, so the kind survived the file
+    assert_eq!(
+        after.last_list(topic).unwrap().list_entries,
+        before.last_list(topic).unwrap().list_entries
+    );
+
+    // only what is new since the saved list, exactly as without the restart
+    let diff = after.process(topic, &second, chrono::Utc::now()).unwrap();
+    let expected = before.process(topic, &second, chrono::Utc::now()).unwrap();
+    assert!(!diff.is_empty());
+    assert_eq!(diff, expected);
+    assert!(DedupState::load(&path).unwrap().is_none());
+}
+
+#[test]
+fn auto_mode_does_not_estimate_from_a_restored_list() {
+    let new_processor = || {
+        processor::DtcProcessor::new(
+            config::resolve_topics(None, None, false),
+            &config::resolve_validation(None),
+            &config::TimeResolved {
+                mode: TimeMode::Auto { round_secs: 900 },
+                offset_secs: 0,
+            },
+        )
+    };
+    let topic = "open3e/680_258_StatusDtcHistory";
+    let first = std::fs::read_to_string("tests/testdata/258_1.json").unwrap();
+    let second = std::fs::read_to_string("tests/testdata/258_2.json").unwrap();
+
+    let mut before = new_processor();
+    before.process(topic, &first, chrono::Utc::now()).unwrap();
+    let mut after = new_processor();
+    after.restore(before.dedup_state());
+
+    // the new entries happened somewhere during a downtime of hours
+    let newest = serde_json::from_str::<vilog::dtc::ResponseDtc>(&second)
+        .unwrap()
+        .list_entries
+        .iter()
+        .map(|e| e.date_time.timestamp)
+        .max()
+        .unwrap();
+    let received_at =
+        chrono::DateTime::from_timestamp_millis(newest).unwrap() + chrono::Duration::hours(5);
+    let diff = after.process(topic, &second, received_at).unwrap();
+    assert!(!diff.is_empty());
+    for e in &diff {
+        assert_eq!(e.timestamp_ms(), e.date_time.timestamp);
+    }
+
+    // without the restart the same poll is fresh and shifts the timestamps
+    let shifted = before.process(topic, &second, received_at).unwrap();
+    assert!(
+        shifted
+            .iter()
+            .all(|e| e.timestamp_ms() != e.date_time.timestamp)
+    );
+}
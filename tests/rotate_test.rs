@@ -0,0 +1,68 @@
+use std::fs;
+use std::path::PathBuf;
+
+use vilog::rotate;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("vilog-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("failed to create temp dir");
+    dir
+}
+
+#[test]
+fn rotates_by_size_and_keeps_max_files() {
+    let dir = temp_dir("rotate");
+    let path = dir.join("capture.jsonl");
+    // each line is 10 bytes incl. newline, so two lines fit into one file
+    let mut file = rotate::RotatingFile::new(&path, 20, 2);
+
+    for n in 0..7 {
+        file.write_line(&format!("line-{:04}", n)).unwrap();
+    }
+    file.flush().unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "line-0006\n");
+    assert_eq!(
+        fs::read_to_string(dir.join("capture.jsonl.1")).unwrap(),
+        "line-0004\nline-0005\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("capture.jsonl.2")).unwrap(),
+        "line-0002\nline-0003\n"
+    );
+    assert!(!dir.join("capture.jsonl.3").exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rotates_daily_with_header_and_gzip() {
+    use std::io::Read;
+
+    let dir = temp_dir("rotate-daily");
+    let path = dir.join("events.csv");
+    let day = |d| chrono::NaiveDate::from_ymd_opt(2025, 11, d).unwrap();
+    let mut file = rotate::RotatingFile::new(&path, 0, 1)
+        .with_daily(true)
+        .with_gzip(true)
+        .with_header(Some("time,code".to_string()));
+
+    file.write_line_on("a,F.1", day(10)).unwrap();
+    file.write_line_on("b,F.2", day(10)).unwrap();
+    file.write_line_on("c,F.3", day(11)).unwrap();
+    file.write_line_on("d,F.4", day(12)).unwrap();
+    file.flush().unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "time,code\nd,F.4\n");
+    let mut rotated = String::new();
+    flate2::read::GzDecoder::new(fs::File::open(dir.join("events.csv.1.gz")).unwrap())
+        .read_to_string(&mut rotated)
+        .unwrap();
+    assert_eq!(rotated, "time,code\nc,F.3\n");
+    // only max_files rotated files are kept, none uncompressed
+    assert!(!dir.join("events.csv.2.gz").exists());
+    assert!(!dir.join("events.csv.1").exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,146 @@
+use std::time::{Duration, Instant};
+
+use vilog::{config, schedule};
+
+const TWO_ECUS: &str = r#"
+[topics]
+stagger_secs = 3
+
+[[ecus]]
+address = "0x680"
+
+[[ecus.commands]]
+dids = [266, 264]
+interval_secs = 20
+
+[[ecus.commands]]
+dids = [258]
+interval_secs = 300
+
+[[ecus]]
+ecuid = "VCMU"
+address = "0x6a1"
+"#;
+
+fn topics(toml: &str) -> config::TopicsResolved {
+    let cfg: config::AppConfig = toml::from_str(toml).unwrap();
+    config::resolve_topics(cfg.topics.as_ref(), cfg.ecus.as_deref(), false)
+}
+
+fn taken(schedule: &mut schedule::Schedule, at: Instant) -> Vec<(String, Vec<u16>)> {
+    schedule
+        .take_due(at)
+        .into_iter()
+        .map(|j| (j.ecuid, j.dids))
+        .collect()
+}
+
+#[test]
+fn commands_run_on_their_own_intervals_and_ecus_are_staggered() {
+    let start = Instant::now();
+    let mut schedule = schedule::Schedule::new(&topics(TWO_ECUS), start);
+    let secs = |s: u64| start + Duration::from_secs(s);
+
+    assert_eq!(
+        taken(&mut schedule, start),
+        vec![
+            ("680".to_string(), vec![266, 264]),
+            ("680".to_string(), vec![258])
+        ]
+    );
+    // the second ECU starts 3 s later
+    assert_eq!(schedule.next_due(), Some(secs(3)));
+    assert_eq!(
+        taken(&mut schedule, secs(3)),
+        vec![("VCMU".to_string(), vec![266, 264, 262, 260, 258])]
+    );
+
+    assert_eq!(schedule.next_due(), Some(secs(20)));
+    assert_eq!(
+        taken(&mut schedule, secs(20)),
+        vec![("680".to_string(), vec![266, 264])]
+    );
+    assert_eq!(
+        taken(&mut schedule, secs(40)),
+        vec![("680".to_string(), vec![266, 264])]
+    );
+    // VCMU uses the default interval of 60 s
+    assert_eq!(schedule.next_due(), Some(secs(60)));
+
+    // after a stall every command runs once, missed runs are not caught up
+    assert_eq!(taken(&mut schedule, secs(1000)).len(), 3);
+    assert_eq!(schedule.next_due(), Some(secs(1020)));
+}
+
+#[test]
+fn jitter_stays_within_its_bound() {
+    let toml = r#"
+        [[ecus]]
+        address = "0x680"
+        [[ecus.commands]]
+        interval_secs = 10
+        jitter_secs = 2
+    "#;
+    let start = Instant::now();
+    let mut schedule = schedule::Schedule::new(&topics(toml), start);
+    for run in 0..20u64 {
+        let due = schedule.next_due().unwrap();
+        let planned = start + Duration::from_secs(run * 10);
+        assert!(due >= planned && due <= planned + Duration::from_secs(2));
+        assert_eq!(schedule.take_due(due).len(), 1);
+    }
+}
+
+#[test]
+fn a_gap_shortens_the_interval_until_the_did_is_quiet() {
+    let toml = r#"
+        [topics]
+        gap_interval_secs = 10
+        gap_quiet_polls = 2
+
+        [[ecus]]
+        address = "0x680"
+        [[ecus.commands]]
+        dids = [266, 264]
+        interval_secs = 60
+        [[ecus.commands]]
+        dids = [258]
+        interval_secs = 300
+    "#;
+    let start = Instant::now();
+    let secs = |s: u64| start + Duration::from_secs(s);
+    let mut schedule = schedule::Schedule::new(&topics(toml), start);
+    assert_eq!(schedule.take_due(start).len(), 2);
+
+    let poll = |new_entries: usize, gap: bool| schedule::PollResult {
+        ecuid: "680".to_string(),
+        did: 258,
+        new_entries,
+        gap,
+    };
+    schedule.record(&poll(10, true), secs(1));
+    assert_eq!(schedule.next_due(), Some(secs(11)));
+    assert_eq!(
+        taken(&mut schedule, secs(11)),
+        vec![("680".to_string(), vec![258])]
+    );
+    // still busy: the counter starts over
+    schedule.record(&poll(4, false), secs(12));
+    assert_eq!(schedule.next_due(), Some(secs(21)));
+    schedule.take_due(secs(21));
+    schedule.record(&poll(0, false), secs(22));
+    schedule.take_due(secs(31));
+    schedule.record(&poll(0, false), secs(32));
+
+    // quiet for two polls: back to 300 s
+    assert_eq!(schedule.take_due(secs(41)).len(), 1);
+    assert_eq!(schedule.next_due(), Some(secs(60)));
+    assert_eq!(taken(&mut schedule, secs(60)).len(), 1);
+    assert_eq!(schedule.next_due(), Some(secs(120)));
+    assert!(
+        taken(&mut schedule, secs(339))
+            .iter()
+            .all(|(_, d)| d != &vec![258])
+    );
+    assert_eq!(schedule.next_due(), Some(secs(341)));
+}
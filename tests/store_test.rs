@@ -0,0 +1,138 @@
+use chrono::{DateTime, Duration, Utc};
+
+use vilog::config::{StoreConfig, resolve_store};
+use vilog::dtc::ResponseDtc;
+use vilog::event::EventRecord;
+use vilog::store::{self, EventFilter, EventStore};
+
+fn temp_store(name: &str, retention_days: u32) -> (EventStore, std::path::PathBuf) {
+    let path = std::env::temp_dir().join(format!("vilog-{}-{}.db", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let cfg = resolve_store(Some(&StoreConfig {
+        enabled: Some(true),
+        path: Some(path.display().to_string()),
+        retention_days: Some(retention_days),
+    }));
+    (EventStore::open(&cfg).expect("failed to open store"), path)
+}
+
+fn records(file: &str, ecuid: &str) -> Vec<EventRecord> {
+    let data = std::fs::read_to_string(format!("tests/testdata/{}.json", file)).unwrap();
+    let parsed: ResponseDtc = serde_json::from_str(&data).unwrap();
+    parsed
+        .list_entries
+        .iter()
+        .map(|e| EventRecord::new(e, "250A", ecuid))
+        .collect()
+}
+
+fn utc(s: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+}
+
+#[test]
+fn events_are_stored_once_and_filtered() {
+    let (mut store, path) = temp_store("query", 0);
+    let now = Utc::now();
+
+    assert_eq!(store.insert(&records("258_1", "HPMU"), now).unwrap(), 10);
+    // the next poll repeats nine of them
+    assert_eq!(store.insert(&records("258_2", "HPMU"), now).unwrap(), 1);
+    assert_eq!(store.insert(&records("264_1", "VCMU"), now).unwrap(), 2);
+
+    let all = store.query(&EventFilter::default()).unwrap();
+    assert_eq!(all.len(), 13);
+    assert!(all.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+
+    let warnings = store
+        .query(&EventFilter {
+            code: Some("a.*".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(warnings.len(), 2);
+    assert!(warnings.iter().all(|w| w.ecuid == "VCMU"));
+    assert!(warnings.contains(&records("264_1", "VCMU")[0]));
+
+    let by_severity = |severity: &str| {
+        store
+            .query(&EventFilter {
+                severity: Some(severity.to_string()),
+                ecuid: Some("hpmu".to_string()),
+                ..Default::default()
+            })
+            .unwrap()
+            .len()
+    };
+    assert_eq!(by_severity("debug"), 11);
+    assert_eq!(by_severity("State"), 11);
+    assert_eq!(by_severity("warning"), 0);
+
+    let window = store
+        .query(&EventFilter {
+            since: Some(utc("2025-11-10T09:00:00Z").timestamp_millis()),
+            until: Some(utc("2025-11-10T10:00:00Z").timestamp_millis()),
+            ..Default::default()
+        })
+        .unwrap();
+    let times: Vec<&str> = window.iter().map(|r| r.date_time.as_str()).collect();
+    assert_eq!(times, vec!["2025-11-10 10:12:28", "2025-11-10 10:12:29"]);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn old_events_are_pruned_after_the_retention() {
+    let (mut store, path) = temp_store("prune", 30);
+    let now = Utc::now();
+    store
+        .insert(&records("258_1", "HPMU"), now - Duration::days(31))
+        .unwrap();
+    store.insert(&records("264_1", "HPMU"), now).unwrap();
+
+    assert_eq!(store.prune(now).unwrap(), 10);
+    assert_eq!(store.query(&EventFilter::default()).unwrap().len(), 2);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn time_arguments() {
+    let now = utc("2025-11-13T12:00:00Z");
+    let ms = |s: &str| utc(s).timestamp_millis();
+
+    assert_eq!(
+        store::parse_time_arg("2025-11-13T10:00:00+01:00", now),
+        Ok(ms("2025-11-13T09:00:00Z"))
+    );
+    assert_eq!(
+        store::parse_time_arg("2025-11-10", now),
+        Ok(ms("2025-11-10T00:00:00Z"))
+    );
+    assert_eq!(
+        store::parse_time_arg("90m", now),
+        Ok(ms("2025-11-13T10:30:00Z"))
+    );
+    assert_eq!(
+        store::parse_time_arg("7d", now),
+        Ok(ms("2025-11-06T12:00:00Z"))
+    );
+    assert!(store::parse_time_arg("yesterday", now).is_err());
+    assert!(store::parse_time_arg("", now).is_err());
+    // spans beyond the representable range are errors, not panics
+    assert_eq!(
+        store::parse_time_arg("99999999999d", now),
+        Err("time span '99999999999d' is too large".to_string())
+    );
+    assert!(store::parse_time_arg(&format!("{}s", i64::MAX), now).is_err());
+}
+
+#[test]
+fn table_columns_are_aligned() {
+    let table = store::format_table(&records("264_1", "HPMU")[..1]);
+    assert_eq!(
+        table,
+        "TIME                       SYSTEM  ECU   CODE   SEVERITY  TEXT\n\
+         2025-11-09T17:50:06+00:00  250A    HPMU  A.100  warning   RestoreEepromToDefault\n"
+    );
+}
@@ -0,0 +1,110 @@
+use vilog::dtc::ResponseDtc;
+use vilog::event::EventRecord;
+use vilog::systemd;
+
+fn error_record() -> EventRecord {
+    let list: ResponseDtc = serde_json::from_str(
+        r#"{"Count": 1, "ListEntries": [{"Error": {"ID": 454, "Text": "CompressorHighPressure"},
+            "DateTime": {"DateTime": "2025-11-10 10:12:29", "Timestamp": 1762765949000},
+            "Unknown": 0}]}"#,
+    )
+    .unwrap();
+    EventRecord::new(&list.list_entries[0], "250A", "HPMU")
+}
+
+#[test]
+fn journal_fields_use_the_severity_as_priority() {
+    let fields = systemd::journal_fields(&error_record());
+    let field = |key: &str| {
+        fields
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v.as_str())
+    };
+
+    assert_eq!(field("PRIORITY"), Some("3"));
+    assert_eq!(field("VILOG_CODE"), Some("F.454"));
+    assert_eq!(field("VILOG_ECUID"), Some("HPMU"));
+    assert_eq!(field("VILOG_SYSTEMID"), Some("250A"));
+    assert!(field("MESSAGE").unwrap().contains("CompressorHighPressure"));
+    assert_eq!(systemd::priority("warning"), 4);
+    assert_eq!(systemd::priority("info"), 6);
+}
+
+#[test]
+fn native_protocol_encodes_multiline_values_with_length() {
+    let encoded = systemd::encode(&[
+        ("VILOG_CODE", "F.454".to_string()),
+        ("MESSAGE", "a\nb".to_string()),
+    ]);
+
+    let mut expected = b"VILOG_CODE=F.454\nMESSAGE\n".to_vec();
+    expected.extend_from_slice(&3u64.to_le_bytes());
+    expected.extend_from_slice(b"a\nb\n");
+    assert_eq!(encoded, expected);
+}
+
+#[cfg(unix)]
+fn temp_socket(name: &str) -> (std::os::unix::net::UnixDatagram, String) {
+    let path = std::env::temp_dir().join(format!("vilog-{}-{}.sock", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let socket = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+    socket.set_nonblocking(true).unwrap();
+    (socket, path.display().to_string())
+}
+
+#[cfg(unix)]
+fn received(socket: &std::os::unix::net::UnixDatagram) -> Vec<String> {
+    let mut buf = [0; 4096];
+    let mut messages = Vec::new();
+    while let Ok(n) = socket.recv(&mut buf) {
+        messages.push(String::from_utf8_lossy(&buf[..n]).into_owned());
+    }
+    messages
+}
+
+#[cfg(unix)]
+#[test]
+fn notifier_sends_ready_and_rate_limits_watchdog_pings() {
+    use std::time::{Duration, Instant};
+
+    let (socket, path) = temp_socket("notify");
+    // WatchdogSec=2 -> a ping at most every second
+    let mut notifier = systemd::Notifier::new(Some(&path), Some("2000000"));
+    assert_eq!(notifier.watchdog_interval(), Some(Duration::from_secs(1)));
+
+    let start = Instant::now();
+    notifier.ready("Subscribed to 5 history topics");
+    notifier.watchdog(start + Duration::from_millis(500));
+    assert_eq!(
+        received(&socket),
+        [
+            "READY=1\nSTATUS=Subscribed to 5 history topics",
+            "WATCHDOG=1"
+        ]
+    );
+
+    notifier.watchdog(start + Duration::from_millis(1500));
+    assert_eq!(received(&socket), ["WATCHDOG=1"]);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn journal_sends_one_datagram_per_event() {
+    let (socket, path) = temp_socket("journal");
+    let journal = systemd::Journal::connect_to(&path).unwrap();
+    let record = error_record();
+
+    journal.record(std::slice::from_ref(&record));
+
+    let mut buf = [0; 4096];
+    let n = socket.recv(&mut buf).unwrap();
+    assert_eq!(
+        &buf[..n],
+        systemd::encode(&systemd::journal_fields(&record)).as_slice()
+    );
+
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,159 @@
+use chrono::{DateTime, Utc};
+
+use vilog::clock::{ClockCorrector, TimeMode};
+use vilog::dtc;
+use vilog::validate::{Issue, ValidationPolicy, Validator};
+
+fn entry(date_time: &str, timestamp: i64) -> dtc::ListEntryDtc {
+    let json = format!(
+        r#"{{"State":{{"ID":118,"Text":"FourThreeWayValveInternalBufferPosition"}},
+            "DateTime":{{"DateTime":"{}","Timestamp":{}}},"Unknown":1}}"#,
+        date_time, timestamp
+    );
+    serde_json::from_str(&json).unwrap()
+}
+
+fn now() -> DateTime<Utc> {
+    "2025-11-10T12:00:00Z".parse().unwrap()
+}
+
+#[test]
+fn all_test_data_is_valid() {
+    let validator = Validator::new(ValidationPolicy::Mark, 86400);
+    for name in ["258_1", "258_2", "258_3", "260_1", "264_1"] {
+        let data = std::fs::read_to_string(format!("tests/testdata/{}.json", name)).unwrap();
+        let parsed: dtc::ResponseDtc = serde_json::from_str(&data).unwrap();
+        for e in &parsed.list_entries {
+            assert_eq!(validator.check(e, now()), vec![], "{} {:?}", name, e);
+        }
+    }
+}
+
+#[test]
+fn detects_bad_timestamps() {
+    let validator = Validator::new(ValidationPolicy::Mark, 3600);
+
+    assert!(
+        validator
+            .check(&entry("2025-11-10 10:12:29", 12), now())
+            .contains(&Issue::OutOfRange)
+    );
+    assert!(
+        validator
+            .check(&entry("2025-11-10 10:12:29", i64::MAX), now())
+            .contains(&Issue::OutOfRange)
+    );
+    // two days ahead of the host clock
+    assert!(
+        validator
+            .check(&entry("2025-11-12 13:00:00", 1762948800000), now())
+            .contains(&Issue::Future)
+    );
+    assert_eq!(
+        validator.check(&entry("garbage", 1762765949000), now()),
+        vec![Issue::UnparseableDateTime]
+    );
+    // 1 h 7 min 13 s is no timezone offset
+    assert_eq!(
+        validator.check(&entry("2025-11-10 10:19:42", 1762765949000), now()),
+        vec![Issue::Inconsistent {
+            offset_ms: 4_033_000
+        }]
+    );
+}
+
+#[test]
+fn policies_mark_drop_and_correct() {
+    let good = entry("2025-11-10 10:12:29", 1762765949000);
+    let bad = entry("2025-11-10 10:20:00", 12);
+
+    let mut entries = vec![good.clone(), bad.clone()];
+    Validator::new(ValidationPolicy::Mark, 86400).apply(&mut entries, now());
+    assert_eq!(entries.len(), 2);
+    assert!(!entries[0].suspect);
+    assert!(entries[1].suspect);
+
+    let mut entries = vec![good.clone(), bad.clone()];
+    Validator::new(ValidationPolicy::Drop, 86400).apply(&mut entries, now());
+    assert_eq!(entries, vec![good.clone()]);
+
+    // the offset of the good entry (1 h) is used to rebuild the timestamp
+    let mut entries = vec![good, bad.clone(), entry("nonsense", 13)];
+    Validator::new(ValidationPolicy::Correct, 86400).apply(&mut entries, now());
+    assert_eq!(entries.len(), 2);
+    assert_eq!(
+        entries[1].get_iso8601_from_timestamp(),
+        "2025-11-10T09:20:00+00:00"
+    );
+    // the device timestamp and so the event ID do not depend on the policy
+    assert_eq!(entries[1].date_time.timestamp, 12);
+    assert_eq!(
+        entries[1].event_id("250A", "HPMU"),
+        bad.event_id("250A", "HPMU")
+    );
+}
+
+#[test]
+fn clock_offset_applies_to_the_rebuilt_timestamp() {
+    let mut entries = vec![entry("2025-11-10 10:20:00", 12)];
+    Validator::new(ValidationPolicy::Correct, 86400).apply(&mut entries, now());
+    ClockCorrector::new(TimeMode::Offset, 60).correct(&mut entries, now(), false);
+    assert_eq!(
+        entries[0].get_iso8601_from_timestamp(),
+        "2025-11-10T10:21:00+00:00"
+    );
+
+    // a rebuilt timestamp is no estimate of the device clock
+    let mut entries = vec![entry("2025-11-10 10:20:00", 12)];
+    Validator::new(ValidationPolicy::Correct, 86400).apply(&mut entries, now());
+    let mut corrector = ClockCorrector::new(TimeMode::Auto { round_secs: 900 }, 0);
+    corrector.correct(&mut entries, now(), true);
+    assert_eq!(
+        entries[0].get_iso8601_from_timestamp(),
+        "2025-11-10T10:20:00+00:00"
+    );
+}
+
+#[test]
+fn invalid_timestamp_does_not_panic() {
+    let e = entry("2025-11-10 10:12:29", i64::MAX);
+    assert_eq!(
+        e.get_iso8601_from_timestamp(),
+        format!("invalid:{}", i64::MAX)
+    );
+
+    for policy in [
+        ValidationPolicy::Mark,
+        ValidationPolicy::Drop,
+        ValidationPolicy::Correct,
+    ] {
+        let mut entries = vec![
+            entry("2025-11-10 10:12:29", i64::MIN),
+            entry("2025-11-10 10:12:29", i64::MAX),
+        ];
+        Validator::new(policy, 86400).apply(&mut entries, now());
+        for e in &entries {
+            assert!(e.suspect || e.corrected_timestamp.is_some(), "{:?}", e);
+        }
+    }
+    let validator = Validator::new(ValidationPolicy::Mark, 86400);
+    assert!(
+        validator
+            .check(&entry("2025-11-10 10:12:29", i64::MIN), now())
+            .contains(&Issue::OutOfRange)
+    );
+
+    // unvalidated garbage through the clock correction: the entry keeps its timestamp
+    for mode in [TimeMode::Offset, TimeMode::Auto { round_secs: 900 }] {
+        let mut entries = vec![
+            entry("2025-11-10 10:12:29", i64::MAX),
+            entry("2025-11-10 10:12:29", i64::MIN),
+        ];
+        let mut corrector = ClockCorrector::new(mode, 3600);
+        corrector.correct(&mut entries, now(), true);
+        corrector.correct(&mut entries, now(), true);
+    }
+    let mut entries = vec![entry("2025-11-10 10:12:29", i64::MAX)];
+    ClockCorrector::new(TimeMode::Offset, 3600).correct(&mut entries, now(), false);
+    assert_eq!(entries[0].corrected_timestamp, None);
+}